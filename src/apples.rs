@@ -1,107 +1,153 @@
 use bevy::prelude::*;
 
 use crate::{
+    game::{AppleKind, GameState},
     geometry,
     grid::{Cell, Grid},
-    snake::{Body, MoveEvent, Position},
+    Config,
 };
 
-use rand::Rng;
-
+/// The index of the apple in `GameState::apples`.
 #[derive(Component)]
-pub struct Apple;
+pub struct Apple(pub usize);
 
 #[derive(Event)]
-pub struct AppleEatenEvent;
+pub struct AppleEatenEvent {
+    pub entity: Entity,
+    pub cell: Cell,
+    pub kind: AppleKind,
+}
 
 #[derive(Event)]
-pub struct RelocateAppleEvent(Vec<Cell>);
+pub struct AppleExpiredEvent {
+    pub entity: Entity,
+    pub cell: Cell,
+}
+
+/// Moves the apple to `cell`, it also takes the kind of the newly placed apple.
+#[derive(Event)]
+pub struct RelocateAppleEvent {
+    pub entity: Entity,
+    pub cell: Cell,
+    pub kind: AppleKind,
+}
+
+fn apple_color(kind: AppleKind) -> Color {
+    match kind {
+        AppleKind::Normal => Color::rgb(6.25, 9.4, 9.1),
+        AppleKind::Golden => Color::rgb(9.4, 7.5, 0.5),
+        AppleKind::Poison => Color::rgb(5.0, 0.5, 8.0),
+        AppleKind::SlowDown => Color::rgb(0.5, 2.5, 9.4),
+        AppleKind::SpeedUp => Color::rgb(9.4, 1.0, 0.5),
+    }
+}
 
-pub fn spawn_apple(
+pub fn spawn_apples(
     grid: Res<Grid>,
+    state: Res<GameState>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let mut rng = rand::thread_rng();
-    let idx_x = rng.gen_range(0..grid.max_idx_x);
-    let idx_y = rng.gen_range(0..grid.max_idx_y);
-    let cell = grid.get_cell_from_index(idx_x, idx_y);
-    let square = geometry::get_square(
-        grid.lambda,
-        cell.pos_x,
-        cell.pos_y,
-        &mut meshes,
-        &mut materials,
-    );
-
-    commands.spawn((square, cell, Apple));
+    for (i, apple) in state.apples.iter().enumerate() {
+        let Some(apple) = apple else {
+            continue;
+        };
+        let cell = grid.get_cell(apple.cell);
+        let square = geometry::get_colored_square(
+            grid.lambda,
+            cell.pos_x,
+            cell.pos_y,
+            apple_color(apple.kind),
+            &mut meshes,
+            &mut materials,
+        );
+
+        commands.spawn((square, cell, Apple(i), apple.kind));
+    }
 }
 
-pub fn apple_eaten(
-    mut ev_move: EventReader<MoveEvent>,
-    head: Query<(&Cell, &Position)>,
-    apple: Query<(&Cell, &Apple)>,
-    mut ev_apple_eaten: EventWriter<AppleEatenEvent>,
-) {
-    for MoveEvent(trace) in ev_move.read() {
-        let (c, _) = apple.get_single().unwrap();
+pub fn despawn_apples(mut commands: Commands, apples: Query<Entity, With<Apple>>) {
+    for entity in &apples {
+        commands.entity(entity).despawn();
+    }
+}
 
-        let (head, _) = head.single();
-        if *head == *c {
-            ev_apple_eaten.send(AppleEatenEvent);
-            return;
-        }
+pub fn find_apple(apples: &Query<(Entity, &Apple)>, index: usize) -> Option<Entity> {
+    apples
+        .iter()
+        .find(|(_, Apple(i))| *i == index)
+        .map(|(entity, _)| entity)
+}
 
-        if let Some(_) = trace.iter().find(|tc| *tc == c) {
-            ev_apple_eaten.send(AppleEatenEvent);
-            return;
+pub fn relocate_apple(
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut apples: Query<
+        (
+            &mut Transform,
+            &mut Cell,
+            &mut AppleKind,
+            &Handle<ColorMaterial>,
+        ),
+        With<Apple>,
+    >,
+    mut ev_relocate_apple: EventReader<RelocateAppleEvent>,
+) {
+    for RelocateAppleEvent { entity, cell, kind } in ev_relocate_apple.read() {
+        if let Ok((mut t, mut c, mut k, material)) = apples.get_mut(*entity) {
+            c.set(cell);
+            t.translation.x = cell.pos_x;
+            t.translation.y = cell.pos_y;
+
+            *k = *kind;
+            if let Some(material) = materials.get_mut(material) {
+                material.color = apple_color(*kind);
+            }
         }
     }
 }
 
-pub fn find_free_cells(
-    head: Query<(&Cell, &Position)>,
-    body: Query<(&Cell, &Body)>,
-    mut ev_apple_eaten: EventReader<AppleEatenEvent>,
-    mut ev_spawn_apple: EventWriter<RelocateAppleEvent>,
-) {
-    for _ in ev_apple_eaten.read() {
-        let mut snake_cells: Vec<_> = body.iter().map(|(c, _)| c.clone()).collect();
-        snake_cells.push(head.single().0.clone());
-        ev_spawn_apple.send(RelocateAppleEvent(snake_cells));
+/// Hides the apples that wait for a free cell in the apple zone.
+pub fn hide_waiting_apples(state: Res<GameState>, mut apples: Query<(&Apple, &mut Visibility)>) {
+    for (Apple(i), mut visibility) in &mut apples {
+        *visibility = match state.apples.get(*i) {
+            Some(None) => Visibility::Hidden,
+            _ => Visibility::Inherited,
+        };
     }
 }
 
-pub fn relocate_apple(
-    grid: Res<Grid>,
-    mut apple: Query<(&mut Transform, &mut Cell, &Apple)>,
-    mut ev_spawn_apple: EventReader<RelocateAppleEvent>,
+/// Fades apples with a lifetime out and lets them blink during their last quarter.
+pub fn apple_countdown(
+    time: Res<Time>,
+    config: Res<Config>,
+    state: Res<GameState>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut apples: Query<(&Apple, &AppleKind, &Handle<ColorMaterial>, &mut Visibility)>,
 ) {
-    for RelocateAppleEvent(snake_cells) in ev_spawn_apple.read() {
-        let mut retry = true;
-        while retry {
-            let mut rng = rand::thread_rng();
-            let idx_x = rng.gen_range((-grid.max_idx_x + 1)..grid.max_idx_x);
-            let idx_y = rng.gen_range((-grid.max_idx_y + 1)..grid.max_idx_y);
-            let apple_cell = grid.get_cell_from_index(idx_x, idx_y);
-
-            let mut apple_at_free_spot = true;
-            for snake_cell in snake_cells {
-                if apple_cell == *snake_cell {
-                    apple_at_free_spot = false;
-                    break;
-                }
-            }
+    let Some(lifetime) = config.apple_lifetime else {
+        return;
+    };
 
-            if apple_at_free_spot {
-                let (mut t, mut c, _) = apple.get_single_mut().unwrap();
-                c.set(&apple_cell);
-                t.translation.x = apple_cell.pos_x;
-                t.translation.y = apple_cell.pos_y;
+    for (Apple(i), kind, material, mut visibility) in &mut apples {
+        let Some(ticks_left) = state
+            .apples
+            .get(*i)
+            .and_then(|a| a.and_then(|a| a.ticks_left))
+        else {
+            continue;
+        };
+        let fraction = ticks_left as f32 / lifetime as f32;
 
-                retry = false;
-            }
+        if let Some(material) = materials.get_mut(material) {
+            material.color = apple_color(*kind).with_a(0.3 + 0.7 * fraction);
         }
+
+        let blink_off = fraction < 0.25 && (time.elapsed_seconds() * 8.) as u32 % 2 == 1;
+        *visibility = if blink_off {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
     }
 }
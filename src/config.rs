@@ -0,0 +1,656 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::ecs::system::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::level::Level;
+
+/// One snake per set of steering keys on the keyboard.
+pub const MAX_PLAYERS: u32 = 2;
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub n_vertical_cells: u32, // Must be uneven, ignored if a level file is given
+    pub n_horizontal_cells: u32, // Must be uneven, ignored if a level file is given
+    pub level: Option<PathBuf>,
+    pub bindings: PathBuf, // Settings file of the keys and gamepad buttons, created by the rebinding screen
+    pub seed: Option<u64>, // Seed of the random apples and crabs, every game gets a new one from the time if not set
+    pub n_players: u32,
+    pub controllers: Vec<ControllerKind>, // In player order, players without an entry use the keyboard
+    pub initial_bodylength: u32,
+    pub initial_speed: f32,
+    pub max_speed: Option<f32>, // The speed never rises above it, whether from the speed curve or from apples
+    pub n_elements_per_apple: u32,
+    pub n_apples: u32,
+    pub apple_lifetime: Option<u32>, // In ticks, apples never expire if not set
+    pub score_increment: u32,
+    pub scoring: ScoringRules,
+    pub speed_curve: SpeedCurve,
+    pub apple_kinds: AppleKindConfig,
+    pub crabs: CrabConfig,
+    pub win_condition: WinCondition,
+    pub boundary: BoundaryMode,
+    pub fullscreen: bool,
+}
+
+/// Who steers a snake, see the `controller` module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControllerKind {
+    #[default]
+    Keyboard,
+    /// Heads straight for the nearest apple.
+    Greedy,
+    /// Follows a shortest path to the nearest apple.
+    ShortestPath,
+    /// Walks a cycle through the whole board.
+    Hamiltonian,
+}
+
+/// What happens when the snake leaves the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    /// The board is surrounded by walls.
+    #[default]
+    Solid,
+    /// The snake re-enters on the opposite side, the open level has no border walls.
+    Wrap,
+}
+
+/// When the level is complete. Filling the board always completes it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WinCondition {
+    FillBoard,
+    Apples(u32),
+    /// Reach the score before `time_limit` seconds of play have passed.
+    Score {
+        target: f32,
+        time_limit: f32,
+    },
+}
+
+/// Presets of the initial speed, the growth per apple, the speed curve and
+/// the maximum speed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    fn settings(&self) -> (f32, u32, SpeedCurve, Option<f32>) {
+        match self {
+            Difficulty::Easy => (
+                6.,
+                1,
+                SpeedCurve::Linear {
+                    every: 5,
+                    step: 0.5,
+                },
+                Some(12.),
+            ),
+            // The defaults of the config
+            Difficulty::Normal => (10., 1, SpeedCurve::default(), None),
+            Difficulty::Hard => (
+                14.,
+                2,
+                SpeedCurve::Exponential {
+                    every: 2,
+                    factor: 1.1,
+                },
+                Some(35.),
+            ),
+            Difficulty::Insane => (
+                20.,
+                3,
+                SpeedCurve::Exponential {
+                    every: 1,
+                    factor: 1.08,
+                },
+                Some(50.),
+            ),
+        }
+    }
+
+    /// Replaces the settings of the preset in the config.
+    pub fn apply(&self, config: &mut Config) {
+        let (initial_speed, n_elements_per_apple, speed_curve, max_speed) = self.settings();
+        config.initial_speed = initial_speed;
+        config.n_elements_per_apple = n_elements_per_apple;
+        config.speed_curve = speed_curve;
+        config.max_speed = max_speed;
+    }
+
+    /// The preset the config matches, `None` if it was changed.
+    pub fn of(config: &Config) -> Option<Difficulty> {
+        Difficulty::ALL.into_iter().find(|difficulty| {
+            let (initial_speed, n_elements_per_apple, speed_curve, max_speed) =
+                difficulty.settings();
+            config.initial_speed == initial_speed
+                && config.n_elements_per_apple == n_elements_per_apple
+                && config.speed_curve == speed_curve
+                && config.max_speed == max_speed
+        })
+    }
+}
+
+/// How eating scores on top of the `score_increment * speed^2` of an apple.
+/// The defaults add nothing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringRules {
+    pub combo_ticks: u32, // Apples eaten within that many ticks of the one before continue a combo, 0 turns combos off
+    pub combo_step: f32, // Added to the multiplier of an apple for every apple of the combo before it
+    pub max_combo_multiplier: f32,
+    pub length_bonus: f32, // Points per segment of the snake for every apple eaten
+    pub time_bonus: Option<TimeBonus>,
+}
+
+/// Points for completing the level faster than `par_time` seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimeBonus {
+    pub par_time: f32,
+    pub points_per_second: f32,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        ScoringRules {
+            combo_ticks: 0,
+            combo_step: 0.5,
+            max_combo_multiplier: 4.,
+            length_bonus: 0.,
+            time_bonus: None,
+        }
+    }
+}
+
+/// How the speed rises as a snake eats apples. The apples are counted per
+/// snake without `score_increment`. Only the stepwise curve may slow down.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SpeedCurve {
+    /// Adds `step` cells per second every `every` apples.
+    Linear { every: u32, step: f32 },
+    /// Multiplies the speed by `factor` every `every` apples.
+    Exponential { every: u32, factor: f32 },
+    /// Sets the speed once the number of apples is reached, as `(apples, speed)`.
+    Stepwise(Vec<(u32, f32)>),
+    /// Follows the curve but never goes faster than `max`.
+    Capped { curve: Box<SpeedCurve>, max: f32 },
+}
+
+impl Default for SpeedCurve {
+    fn default() -> Self {
+        SpeedCurve::Exponential {
+            every: 3,
+            factor: 1.1,
+        }
+    }
+}
+
+impl SpeedCurve {
+    /// The speed after eating the `n_eaten`-th apple at `speed`.
+    pub fn next_speed(&self, speed: f32, n_eaten: u32) -> f32 {
+        match self {
+            SpeedCurve::Linear { every, step } if n_eaten.is_multiple_of(*every) => speed + step,
+            SpeedCurve::Exponential { every, factor } if n_eaten.is_multiple_of(*every) => {
+                speed * factor
+            }
+            SpeedCurve::Stepwise(steps) => steps
+                .iter()
+                .find(|(apples, _)| *apples == n_eaten)
+                .map_or(speed, |(_, step_speed)| *step_speed),
+            SpeedCurve::Capped { curve, max } => curve.next_speed(speed, n_eaten).min(*max),
+            _ => speed,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let valid = match self {
+            SpeedCurve::Linear { every, step } => *every > 0 && *step >= 0. && step.is_finite(),
+            SpeedCurve::Exponential { every, factor } => {
+                *every > 0 && *factor >= 1. && factor.is_finite()
+            }
+            SpeedCurve::Stepwise(steps) => steps
+                .iter()
+                .all(|(_, speed)| *speed > 0. && speed.is_finite()),
+            SpeedCurve::Capped { curve, max } => {
+                curve.validate()?;
+                *max > 0. && max.is_finite()
+            }
+        };
+        if !valid {
+            return Err(ConfigError::InvalidSpeedCurve(self.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppleKindConfig {
+    // Probabilities that a spawned apple is of the given kind, all others are normal apples
+    pub golden_probability: f32,
+    pub poison_probability: f32,
+    pub slow_down_probability: f32,
+    pub speed_up_probability: f32,
+    pub golden_score_factor: f32,
+    pub poison_shrink: u32,
+    pub speed_factor: f32,
+}
+
+impl Default for AppleKindConfig {
+    fn default() -> Self {
+        AppleKindConfig {
+            golden_probability: 0.1,
+            poison_probability: 0.05,
+            slow_down_probability: 0.05,
+            speed_up_probability: 0.05,
+            golden_score_factor: 5.,
+            poison_shrink: 3,
+            speed_factor: 1.25,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrabConfig {
+    pub n_crabs: u32,
+    pub behavior: CrabBehavior,
+    pub ticks_per_move: u32, // Crabs move once every that many ticks of the snake
+    pub score_factor: f32,   // Eating a crab scores that many normal apples
+}
+
+/// What the crabs walk towards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrabBehavior {
+    /// The nearest apple, which the crab takes away from the snakes.
+    #[default]
+    Apples,
+    /// The nearest head of a snake.
+    Chase,
+}
+
+impl Default for CrabConfig {
+    fn default() -> Self {
+        CrabConfig {
+            n_crabs: 0,
+            behavior: CrabBehavior::Apples,
+            ticks_per_move: 2,
+            score_factor: 3.,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            n_vertical_cells: 25,
+            n_horizontal_cells: 45,
+            level: None,
+            bindings: PathBuf::from("bindings.ron"),
+            seed: None,
+            n_players: 1,
+            controllers: Vec::new(),
+            initial_bodylength: 10,
+            initial_speed: 10.,
+            max_speed: None,
+            n_elements_per_apple: 1,
+            n_apples: 1,
+            apple_lifetime: None,
+            score_increment: 1,
+            scoring: ScoringRules::default(),
+            speed_curve: SpeedCurve::default(),
+            apple_kinds: AppleKindConfig::default(),
+            crabs: CrabConfig::default(),
+            win_condition: WinCondition::FillBoard,
+            boundary: BoundaryMode::Solid,
+            fullscreen: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        err: String,
+    },
+    Write {
+        path: PathBuf,
+        err: String,
+    },
+    Parse(String),
+    UnknownFormat(String),
+    EvenCellCount {
+        axis: &'static str,
+        n_cells: u32,
+    },
+    BoardTooSmall {
+        axis: &'static str,
+        n_cells: u32,
+    },
+    InvalidPlayerCount(u32),
+    TooManyControllers {
+        n_controllers: usize,
+        n_players: u32,
+    },
+    InvalidBodyLength {
+        length: u32,
+        max: u32,
+    },
+    InvalidSpeed(f32),
+    InvalidMaxSpeed {
+        max_speed: f32,
+        initial_speed: f32,
+    },
+    InvalidAppleCount {
+        n_apples: u32,
+        max: u32,
+    },
+    ZeroScoreIncrement,
+    InvalidAppleProbabilities,
+    InvalidSpeedFactor(f32),
+    ZeroAppleLifetime,
+    InvalidCrabCount {
+        n_crabs: u32,
+        max: u32,
+    },
+    ZeroCrabTicks,
+    InvalidCrabScoreFactor(f32),
+    InvalidScoringRules(&'static str),
+    InvalidSpeedCurve(SpeedCurve),
+    InvalidWinCondition(WinCondition),
+    InvalidLevel(String),
+    InvalidBindings(String),
+    InvalidReplay(String),
+    InvalidHighScores(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, err } => write!(f, "cannot read {}: {err}", path.display()),
+            ConfigError::Write { path, err } => {
+                write!(f, "cannot write {}: {err}", path.display())
+            }
+            ConfigError::Parse(err) => write!(f, "cannot parse config file: {err}"),
+            ConfigError::UnknownFormat(ext) => {
+                write!(f, "unknown config format '{ext}', expected .ron or .toml")
+            }
+            ConfigError::EvenCellCount { axis, n_cells } => {
+                write!(
+                    f,
+                    "the number of {axis} cells must be uneven, got {n_cells}"
+                )
+            }
+            ConfigError::BoardTooSmall { axis, n_cells } => {
+                write!(
+                    f,
+                    "the number of {axis} cells must be at least 5, got {n_cells}"
+                )
+            }
+            ConfigError::InvalidPlayerCount(n_players) => write!(
+                f,
+                "the number of players must be between 1 and {MAX_PLAYERS}, got {n_players}"
+            ),
+            ConfigError::TooManyControllers {
+                n_controllers,
+                n_players,
+            } => write!(
+                f,
+                "{n_controllers} controllers are given for only {n_players} players"
+            ),
+            ConfigError::InvalidBodyLength { length, max } => write!(
+                f,
+                "the initial body length must be between 1 and {max}, got {length}"
+            ),
+            ConfigError::InvalidSpeed(speed) => {
+                write!(f, "the initial speed must be positive, got {speed}")
+            }
+            ConfigError::InvalidMaxSpeed {
+                max_speed,
+                initial_speed,
+            } => write!(
+                f,
+                "the maximum speed must be at least the initial speed of {initial_speed}, got {max_speed}"
+            ),
+            ConfigError::InvalidAppleCount { n_apples, max } => {
+                write!(
+                    f,
+                    "the number of apples must be between 1 and {max}, got {n_apples}"
+                )
+            }
+            ConfigError::ZeroScoreIncrement => write!(f, "the score increment must not be 0"),
+            ConfigError::InvalidAppleProbabilities => write!(
+                f,
+                "the apple kind probabilities must be between 0 and 1 and sum up to at most 1"
+            ),
+            ConfigError::InvalidSpeedFactor(factor) => {
+                write!(f, "the apple speed factor must be at least 1, got {factor}")
+            }
+            ConfigError::ZeroAppleLifetime => write!(f, "the apple lifetime must not be 0"),
+            ConfigError::InvalidCrabCount { n_crabs, max } => write!(
+                f,
+                "the number of crabs must be at most {max} besides the apples, got {n_crabs}"
+            ),
+            ConfigError::ZeroCrabTicks => write!(f, "the crab ticks per move must not be 0"),
+            ConfigError::InvalidCrabScoreFactor(factor) => {
+                write!(
+                    f,
+                    "the crab score factor must not be negative, got {factor}"
+                )
+            }
+            ConfigError::InvalidScoringRules(reason) => {
+                write!(f, "invalid scoring rules: {reason}")
+            }
+            ConfigError::InvalidSpeedCurve(curve) => {
+                write!(
+                    f,
+                    "the speed curve {curve:?} must keep the speed positive, only a stepwise curve may slow down"
+                )
+            }
+            ConfigError::InvalidWinCondition(condition) => {
+                write!(f, "the win condition {condition:?} can never be reached")
+            }
+            ConfigError::InvalidLevel(err) => write!(f, "invalid level: {err}"),
+            ConfigError::InvalidBindings(err) => write!(f, "invalid input bindings: {err}"),
+            ConfigError::InvalidReplay(err) => write!(f, "invalid replay: {err}"),
+            ConfigError::InvalidHighScores(err) => write!(f, "invalid high scores: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads the whole file, the error names the path.
+pub(crate) fn read_file(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|err| ConfigError::Read {
+        path: path.to_path_buf(),
+        err: err.to_string(),
+    })
+}
+
+/// Writes the value as RON, creating the directory of the file if needed.
+/// Files people may edit by hand are written `pretty`, others compact.
+pub(crate) fn write_ron<T: Serialize>(
+    path: &Path,
+    value: &T,
+    pretty: bool,
+) -> Result<(), ConfigError> {
+    let write_error = |err: String| ConfigError::Write {
+        path: path.to_path_buf(),
+        err,
+    };
+    let content = match pretty {
+        true => ron::ser::to_string_pretty(value, Default::default()),
+        false => ron::to_string(value),
+    }
+    .map_err(|err| write_error(err.to_string()))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| write_error(err.to_string()))?;
+    }
+    fs::write(path, content).map_err(|err| write_error(err.to_string()))
+}
+
+impl Config {
+    /// Reads a config from a `.ron` or `.toml` file. Missing fields keep their default value.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content = read_file(path)?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+
+        match extension {
+            "ron" => ron::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            "toml" => toml::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            _ => Err(ConfigError::UnknownFormat(extension.to_string())),
+        }
+    }
+
+    /// The level file of the config, or the open rectangle of the configured size.
+    /// Fails if the snake or the apples do not fit into the level.
+    pub fn load_level(&self) -> Result<Level, ConfigError> {
+        let level = match &self.level {
+            Some(path) => Level::load(path)?,
+            None => Level::open(self),
+        };
+        level.check(self)?;
+
+        Ok(level)
+    }
+
+    /// Who steers each player, players without an entry in `controllers` use the keyboard.
+    pub fn controller_kinds(&self) -> Vec<ControllerKind> {
+        (0..self.n_players as usize)
+            .map(|player| {
+                self.controllers
+                    .get(player)
+                    .copied()
+                    .unwrap_or(ControllerKind::Keyboard)
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (axis, n_cells) in [
+            ("horizontal", self.n_horizontal_cells),
+            ("vertical", self.n_vertical_cells),
+        ] {
+            if n_cells.is_multiple_of(2) {
+                return Err(ConfigError::EvenCellCount { axis, n_cells });
+            }
+            if n_cells < 5 {
+                return Err(ConfigError::BoardTooSmall { axis, n_cells });
+            }
+        }
+
+        if !(1..=MAX_PLAYERS).contains(&self.n_players) {
+            return Err(ConfigError::InvalidPlayerCount(self.n_players));
+        }
+        if self.controllers.len() > self.n_players as usize {
+            return Err(ConfigError::TooManyControllers {
+                n_controllers: self.controllers.len(),
+                n_players: self.n_players,
+            });
+        }
+
+        if self.initial_speed <= 0. || !self.initial_speed.is_finite() {
+            return Err(ConfigError::InvalidSpeed(self.initial_speed));
+        }
+        if let Some(max_speed) = self.max_speed {
+            if max_speed < self.initial_speed || !max_speed.is_finite() {
+                return Err(ConfigError::InvalidMaxSpeed {
+                    max_speed,
+                    initial_speed: self.initial_speed,
+                });
+            }
+        }
+
+        if self.apple_lifetime == Some(0) {
+            return Err(ConfigError::ZeroAppleLifetime);
+        }
+
+        if self.score_increment == 0 {
+            return Err(ConfigError::ZeroScoreIncrement);
+        }
+
+        let scoring = &self.scoring;
+        if scoring.combo_step < 0. || !scoring.combo_step.is_finite() {
+            return Err(ConfigError::InvalidScoringRules(
+                "the combo step must not be negative",
+            ));
+        }
+        if scoring.max_combo_multiplier < 1. || !scoring.max_combo_multiplier.is_finite() {
+            return Err(ConfigError::InvalidScoringRules(
+                "the maximum combo multiplier must be at least 1",
+            ));
+        }
+        if scoring.length_bonus < 0. || !scoring.length_bonus.is_finite() {
+            return Err(ConfigError::InvalidScoringRules(
+                "the length bonus must not be negative",
+            ));
+        }
+        if let Some(bonus) = scoring.time_bonus {
+            let valid = bonus.par_time > 0.
+                && bonus.par_time.is_finite()
+                && bonus.points_per_second >= 0.
+                && bonus.points_per_second.is_finite();
+            if !valid {
+                return Err(ConfigError::InvalidScoringRules(
+                    "the time bonus needs a positive par time and points per second that are not negative",
+                ));
+            }
+        }
+        self.speed_curve.validate()?;
+
+        let kinds = &self.apple_kinds;
+        let probabilities = [
+            kinds.golden_probability,
+            kinds.poison_probability,
+            kinds.slow_down_probability,
+            kinds.speed_up_probability,
+        ];
+        if probabilities.iter().any(|p| !(0. ..=1.).contains(p))
+            || probabilities.iter().sum::<f32>() > 1.
+        {
+            return Err(ConfigError::InvalidAppleProbabilities);
+        }
+
+        if kinds.speed_factor < 1. || !kinds.speed_factor.is_finite() {
+            return Err(ConfigError::InvalidSpeedFactor(kinds.speed_factor));
+        }
+
+        if self.crabs.ticks_per_move == 0 {
+            return Err(ConfigError::ZeroCrabTicks);
+        }
+        if self.crabs.score_factor < 0. || !self.crabs.score_factor.is_finite() {
+            return Err(ConfigError::InvalidCrabScoreFactor(self.crabs.score_factor));
+        }
+
+        let reachable = match self.win_condition {
+            WinCondition::FillBoard => true,
+            WinCondition::Apples(n_apples) => n_apples > 0,
+            WinCondition::Score { target, time_limit } => target > 0. && time_limit > 0.,
+        };
+        if !reachable {
+            return Err(ConfigError::InvalidWinCondition(self.win_condition));
+        }
+
+        Ok(())
+    }
+}
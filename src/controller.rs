@@ -0,0 +1,565 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::math::IVec2;
+
+use crate::{
+    game::{self, AppleKind, Direction, GameState, Snake},
+    level::Level,
+    Config, ControllerKind,
+};
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Steers one snake of a [`GameState`], either for a human or as a bot.
+pub trait SnakeController: Send + Sync {
+    /// The direction for the next step of the snake with the index, `None`
+    /// keeps the current one.
+    fn next_direction(&mut self, state: &GameState, snake: usize) -> Option<Direction>;
+
+    /// Called for every direction requested with the set of steering bindings
+    /// `slot` since the last frame, bots ignore it.
+    fn steer(&mut self, _slot: usize, _direction: Direction) {}
+}
+
+/// The controllers of all players in order, see `Config::controllers`.
+pub fn from_config(config: &Config, level: &Level) -> Vec<Box<dyn SnakeController>> {
+    let kinds = config.controller_kinds();
+    let n_keyboards = kinds
+        .iter()
+        .filter(|kind| **kind == ControllerKind::Keyboard)
+        .count();
+
+    let mut slots = 0..;
+    kinds
+        .into_iter()
+        .map(|kind| -> Box<dyn SnakeController> {
+            match kind {
+                // A single human player may use every set of bindings
+                ControllerKind::Keyboard if n_keyboards == 1 => {
+                    Box::new(KeyboardController::new(None))
+                }
+                ControllerKind::Keyboard => Box::new(KeyboardController::new(slots.next())),
+                ControllerKind::Greedy => Box::new(GreedyController),
+                ControllerKind::ShortestPath => Box::new(ShortestPathController),
+                ControllerKind::Hamiltonian => Box::new(HamiltonianController::new(level)),
+            }
+        })
+        .collect()
+}
+
+/// The directions of all snakes for the next step, in player order.
+pub fn next_directions(
+    controllers: &mut [Box<dyn SnakeController>],
+    state: &GameState,
+) -> Vec<Option<Direction>> {
+    controllers
+        .iter_mut()
+        .enumerate()
+        .map(|(snake, controller)| controller.next_direction(state, snake))
+        .collect()
+}
+
+/// Steps the game with the directions of the controllers until it is over or
+/// reaches `max_ticks`. `on_step` gets the state after every step and the
+/// directions it was given.
+pub fn play(
+    state: &mut GameState,
+    controllers: &mut [Box<dyn SnakeController>],
+    max_ticks: u32,
+    mut on_step: impl FnMut(&GameState, &[Option<Direction>]),
+) {
+    while !state.game_over && state.ticks() < max_ticks {
+        let inputs = next_directions(controllers, state);
+        state.step(&inputs);
+        on_step(state, &inputs);
+    }
+}
+
+/// Number of turns a human player can queue ahead of the snake.
+pub const INPUT_QUEUE_LEN: usize = 3;
+
+/// A human player at the keyboard or a gamepad. Turns are queued and taken one
+/// per step, so quick successive presses within one cell are not lost.
+pub struct KeyboardController {
+    /// The set of steering bindings of the player, `None` for all of them.
+    slot: Option<usize>,
+    queue: VecDeque<Direction>,
+    /// Direction of the snake after the last step, until the first step `None`.
+    heading: Option<Direction>,
+}
+
+impl KeyboardController {
+    pub fn new(slot: Option<usize>) -> KeyboardController {
+        KeyboardController {
+            slot,
+            queue: VecDeque::with_capacity(INPUT_QUEUE_LEN),
+            heading: None,
+        }
+    }
+
+    /// Queues the turn unless the queue is full, or it repeats or reverses the
+    /// direction the snake will have once the queued turns are taken.
+    fn request(&mut self, direction: Direction) {
+        let last = self.queue.back().copied().or(self.heading);
+        if self.queue.len() == INPUT_QUEUE_LEN
+            || last.is_some_and(|last| direction == last || direction == last.opposite())
+        {
+            return;
+        }
+        self.queue.push_back(direction);
+    }
+}
+
+impl SnakeController for KeyboardController {
+    fn next_direction(&mut self, state: &GameState, snake: usize) -> Option<Direction> {
+        let direction = self.queue.pop_front();
+        self.heading = direction.or(Some(state.snakes[snake].direction));
+        direction
+    }
+
+    fn steer(&mut self, slot: usize, direction: Direction) {
+        if self.slot.is_none_or(|own| own == slot) {
+            self.request(direction);
+        }
+    }
+}
+
+/// Turns and the cells they lead to that do not kill the snake right away.
+fn safe_moves(state: &GameState, snake: usize) -> impl Iterator<Item = (Direction, IVec2)> + '_ {
+    let current = &state.snakes[snake];
+    let head = current.head();
+    let reverse = current.direction.opposite();
+    DIRECTIONS
+        .into_iter()
+        .filter(move |d| *d != reverse)
+        .map(move |d| (d, state.neighbor(head, d)))
+        .filter(|(_, cell)| state.is_safe(*cell))
+}
+
+/// Cells of the apples worth eating. Poison apples are only eaten if there
+/// is nothing else, so they do not block the board forever.
+fn targets(state: &GameState) -> Vec<IVec2> {
+    let wholesome: Vec<_> = state
+        .apples
+        .iter()
+        .flatten()
+        .filter(|a| a.kind != AppleKind::Poison)
+        .map(|a| a.cell)
+        .collect();
+    match wholesome.is_empty() {
+        true => state.apples.iter().flatten().map(|a| a.cell).collect(),
+        false => wholesome,
+    }
+}
+
+fn nearest_apple(state: &GameState, cell: IVec2) -> Option<IVec2> {
+    targets(state)
+        .into_iter()
+        .min_by_key(|apple| game::distance(*apple, cell))
+}
+
+/// Number of safe cells reachable from `start`, at most `limit`.
+fn reachable_area(state: &GameState, start: IVec2, limit: usize) -> usize {
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(cell) = queue.pop_front() {
+        if visited.len() >= limit {
+            break;
+        }
+        for direction in DIRECTIONS {
+            let next = state.neighbor(cell, direction);
+            if state.is_safe(next) && visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    visited.len()
+}
+
+/// Heads straight for the nearest apple, it only avoids dying on the next step.
+pub struct GreedyController;
+
+impl SnakeController for GreedyController {
+    fn next_direction(&mut self, state: &GameState, snake: usize) -> Option<Direction> {
+        let target = nearest_apple(state, state.snakes[snake].head());
+        safe_moves(state, snake)
+            .min_by_key(|(_, cell)| target.map_or(0, |t| game::distance(*cell, t)))
+            .map(|(direction, _)| direction)
+    }
+}
+
+/// Follows a shortest path over the currently free cells to the nearest apple,
+/// as long as its first step leaves room for the whole snake. Otherwise it
+/// takes the turn that leaves the most room.
+pub struct ShortestPathController;
+
+impl ShortestPathController {
+    /// Breadth-first search, every queued cell remembers the first turn leading to it.
+    fn path_to_apple(state: &GameState, snake: usize) -> Option<Direction> {
+        let targets = targets(state);
+        let mut visited = HashSet::from([state.snakes[snake].head()]);
+        let mut queue = VecDeque::new();
+        for (direction, cell) in safe_moves(state, snake) {
+            visited.insert(cell);
+            queue.push_back((cell, direction));
+        }
+
+        while let Some((cell, first)) = queue.pop_front() {
+            if targets.contains(&cell) {
+                return Some(first);
+            }
+            for direction in DIRECTIONS {
+                let next = state.neighbor(cell, direction);
+                if state.is_safe(next) && visited.insert(next) {
+                    queue.push_back((next, first));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl SnakeController for ShortestPathController {
+    fn next_direction(&mut self, state: &GameState, snake: usize) -> Option<Direction> {
+        let length = state.snakes[snake].body.len();
+        if let Some(direction) = Self::path_to_apple(state, snake) {
+            let cell = state.neighbor(state.snakes[snake].head(), direction);
+            if reachable_area(state, cell, length) >= length {
+                return Some(direction);
+            }
+        }
+
+        let limit = state.level.board.n_cells();
+        safe_moves(state, snake)
+            .max_by_key(|(_, cell)| reachable_area(state, *cell, limit))
+            .map(|(direction, _)| direction)
+    }
+}
+
+/// Walks a cycle through every free cell, which never runs into the own body
+/// once the snake is on it. Only works for levels whose free cells form a
+/// rectangle. On boards with an odd number of free cells one corner is left
+/// out, the snake only takes a detour through it to eat an apple. There the
+/// last few apples of a full board may still be out of reach.
+///
+/// While the body lies in the order of the cycle the snake takes shortcuts
+/// towards the next apple, as long as it neither passes the apple nor comes
+/// close to its own tail. Off the cycle, or if it is blocked, it steers like
+/// [`ShortestPathController`].
+pub struct HamiltonianController {
+    cycle: Vec<IVec2>,
+    /// Position of every cell in `cycle`.
+    index: HashMap<IVec2, usize>,
+    /// The corner left out, the cell leading into it and the cell after it.
+    detour: Option<Detour>,
+}
+
+#[derive(Clone, Copy)]
+struct Detour {
+    entry: IVec2,
+    corner: IVec2,
+    exit: IVec2,
+}
+
+impl HamiltonianController {
+    pub fn new(level: &Level) -> HamiltonianController {
+        let cycle = hamiltonian_cycle(level);
+        let index: HashMap<_, _> = cycle.iter().enumerate().map(|(i, c)| (*c, i)).collect();
+
+        // The corner replaces the cell between two of its neighbours on the cycle
+        let corner = level
+            .board
+            .cells()
+            .find(|cell| !cycle.is_empty() && level.is_free(*cell) && !index.contains_key(cell));
+        let detour = corner.and_then(|corner| {
+            let neighbors: Vec<_> = DIRECTIONS.iter().map(|d| corner + d.delta()).collect();
+            neighbors.iter().find_map(|entry| {
+                let exit = cycle[(index.get(entry)? + 2) % cycle.len()];
+                neighbors.contains(&exit).then_some(Detour {
+                    entry: *entry,
+                    corner,
+                    exit,
+                })
+            })
+        });
+
+        HamiltonianController {
+            cycle,
+            index,
+            detour,
+        }
+    }
+
+    /// Number of steps along the cycle from `a` to `b`.
+    fn ahead(&self, a: IVec2, b: IVec2) -> Option<usize> {
+        let n = self.cycle.len();
+        Some((self.index.get(&b)? + n - self.index.get(&a)?) % n)
+    }
+
+    /// Free cells along the cycle between the head and the tail, `None` unless
+    /// every segment lies further along the cycle than the one behind it.
+    fn gap(&self, snake: &Snake) -> Option<usize> {
+        let mut span = 0;
+        for (segment, behind) in snake.body.iter().zip(snake.body.iter().skip(1)) {
+            match self.ahead(*behind, *segment)? {
+                0 => return None,
+                steps => span += steps,
+            }
+        }
+
+        (span < self.cycle.len()).then(|| self.cycle.len() - span - 1)
+    }
+
+    /// Whether the head may jump `steps` cells ahead along the cycle without
+    /// catching up with its tail, even if it eats an apple where it lands.
+    fn keeps_gap(&self, state: &GameState, snake: &Snake, steps: usize) -> bool {
+        let growth = snake.pending_growth() + state.config().n_elements_per_apple;
+        self.gap(snake)
+            .is_some_and(|gap| gap >= steps + growth as usize)
+    }
+}
+
+impl SnakeController for HamiltonianController {
+    fn next_direction(&mut self, state: &GameState, snake: usize) -> Option<Direction> {
+        let current = &state.snakes[snake];
+        let head = current.head();
+        let targets = targets(state);
+
+        if let Some(detour) = self.detour {
+            // Going through the corner takes as long as the two cells it replaces
+            if head == detour.entry
+                && state
+                    .apples
+                    .iter()
+                    .flatten()
+                    .any(|a| a.cell == detour.corner)
+                && self.keeps_gap(state, current, 0)
+            {
+                if let Some((direction, _)) =
+                    safe_moves(state, snake).find(|(_, cell)| *cell == detour.corner)
+                {
+                    return Some(direction);
+                }
+            }
+        }
+        if let Some(detour) = self.detour.filter(|d| d.corner == head) {
+            let exit = safe_moves(state, snake).find(|(_, cell)| *cell == detour.exit);
+            if let Some((direction, _)) = exit {
+                return Some(direction);
+            }
+        }
+
+        // The furthest jump that neither passes the next apple nor the tail
+        let apple = targets
+            .iter()
+            .map(|t| match self.detour {
+                Some(detour) if detour.corner == *t => detour.entry,
+                _ => *t,
+            })
+            .filter_map(|t| self.ahead(head, t))
+            .filter(|steps| *steps > 0)
+            .min();
+        let shortcut = safe_moves(state, snake)
+            .filter_map(|(direction, cell)| Some((direction, self.ahead(head, cell)?)))
+            .filter(|(_, steps)| *steps > 0 && apple.is_none_or(|apple| *steps <= apple))
+            .filter(|(_, steps)| *steps == 1 || self.keeps_gap(state, current, *steps))
+            .max_by_key(|(_, steps)| *steps);
+        if let Some((direction, _)) = shortcut {
+            return Some(direction);
+        }
+
+        ShortestPathController.next_direction(state, snake)
+    }
+}
+
+/// The free cells of the level in the order of a Hamiltonian cycle, empty if
+/// they do not form a rectangle of at least 2x2 cells.
+fn hamiltonian_cycle(level: &Level) -> Vec<IVec2> {
+    let free: Vec<_> = level
+        .board
+        .cells()
+        .filter(|cell| level.is_free(*cell))
+        .collect();
+    let Some(min) = free.iter().copied().reduce(IVec2::min) else {
+        return Vec::new();
+    };
+    let max = free.iter().copied().reduce(IVec2::max).unwrap();
+    let size = max - min + IVec2::ONE;
+    if (size.x * size.y) as usize != free.len() || size.x < 2 || size.y < 2 {
+        return Vec::new();
+    }
+
+    let cycle = if size.y % 2 == 0 {
+        comb(size.x, size.y)
+    } else if size.x % 2 == 0 {
+        comb(size.y, size.x)
+            .into_iter()
+            .map(|cell| IVec2::new(cell.y, cell.x))
+            .collect()
+    } else {
+        comb_with_top_row(size.x, size.y)
+    };
+
+    cycle.into_iter().map(|cell| min + cell).collect()
+}
+
+/// A cycle through a `width` x `height` rectangle, `height` must be even. The
+/// rows are walked back and forth from column 1 on, column 0 leads back down.
+fn comb(width: i32, height: i32) -> Vec<IVec2> {
+    let mut cells = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for i in 1..width {
+            let x = if y % 2 == 0 { i } else { width - i };
+            cells.push(IVec2::new(x, y));
+        }
+    }
+    for y in (0..height).rev() {
+        cells.push(IVec2::new(0, y));
+    }
+
+    cells
+}
+
+/// A cycle through a rectangle with an uneven `width` and `height` but its top
+/// right corner. The comb below the top row takes detours into the top row.
+fn comb_with_top_row(width: i32, height: i32) -> Vec<IVec2> {
+    let base = comb(width, height - 1);
+    let below_top = height - 2;
+    let mut cells = Vec::with_capacity((width * height) as usize);
+    for (i, cell) in base.iter().enumerate() {
+        cells.push(*cell);
+        let next = base[(i + 1) % base.len()];
+        if cell.y == below_top && next.y == below_top && next.x + 1 == cell.x && next.x % 2 == 0 {
+            cells.push(IVec2::new(cell.x, height - 1));
+            cells.push(IVec2::new(next.x, height - 1));
+        }
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays a game per seed with a single bot on an open 15x11 board. Bots
+    /// may never finish a game, see `HamiltonianController`, so the games are
+    /// cut off after `max_ticks`.
+    fn soak(kind: ControllerKind, max_ticks: u32) -> Vec<GameState> {
+        let config = Config {
+            n_horizontal_cells: 15,
+            n_vertical_cells: 11,
+            initial_bodylength: 4,
+            controllers: vec![kind],
+            ..Default::default()
+        };
+        let level = config.load_level().unwrap();
+        (0..10)
+            .map(|seed| {
+                let mut state = GameState::new(&config, &level, seed);
+                let mut controllers = from_config(&config, &level);
+                play(&mut state, &mut controllers, max_ticks, |state, inputs| {
+                    // A reversal would have been ignored
+                    if let Some(direction) = inputs[0] {
+                        assert_eq!(state.snakes[0].direction, direction);
+                    }
+                });
+                state
+            })
+            .collect()
+    }
+
+    fn n_apples(states: &[GameState]) -> Vec<u32> {
+        states.iter().map(|s| s.snakes[0].score.n_apples).collect()
+    }
+
+    /// A keyboard controller after the first step of a snake heading right.
+    fn keyboard() -> (KeyboardController, GameState) {
+        let config = Config::default();
+        let state = GameState::new(&config, &config.load_level().unwrap(), 0);
+        let mut controller = KeyboardController::new(None);
+        assert_eq!(controller.next_direction(&state, 0), None);
+        (controller, state)
+    }
+
+    #[test]
+    fn keyboard_rejects_reversals_and_repeats() {
+        let (mut controller, state) = keyboard();
+        controller.steer(0, Direction::Left);
+        controller.steer(0, Direction::Right);
+        assert_eq!(controller.next_direction(&state, 0), None);
+
+        controller.steer(0, Direction::Up);
+        controller.steer(0, Direction::Down);
+        controller.steer(0, Direction::Up);
+        controller.steer(1, Direction::Left);
+        assert_eq!(controller.next_direction(&state, 0), Some(Direction::Up));
+        assert_eq!(controller.next_direction(&state, 0), Some(Direction::Left));
+        assert_eq!(controller.next_direction(&state, 0), None);
+    }
+
+    #[test]
+    fn keyboard_queues_at_most_input_queue_len_turns() {
+        let (mut controller, state) = keyboard();
+        let turns = [
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+        ];
+        for direction in turns {
+            controller.steer(0, direction);
+        }
+
+        for direction in &turns[..INPUT_QUEUE_LEN] {
+            assert_eq!(controller.next_direction(&state, 0), Some(*direction));
+        }
+        assert_eq!(controller.next_direction(&state, 0), None);
+    }
+
+    #[test]
+    fn keyboard_listens_to_its_own_bindings() {
+        let (_, state) = keyboard();
+        let mut controller = KeyboardController::new(Some(1));
+        controller.steer(0, Direction::Up);
+        controller.steer(1, Direction::Down);
+        assert_eq!(controller.next_direction(&state, 0), Some(Direction::Down));
+    }
+
+    #[test]
+    fn greedy_eats_apples() {
+        let n_apples = n_apples(&soak(ControllerKind::Greedy, 5000));
+        assert!(n_apples.iter().all(|n| *n >= 5), "{n_apples:?}");
+    }
+
+    #[test]
+    fn shortest_path_beats_greedy() {
+        let greedy = n_apples(&soak(ControllerKind::Greedy, 5000));
+        let shortest_path = n_apples(&soak(ControllerKind::ShortestPath, 5000));
+        assert!(shortest_path.iter().all(|n| *n >= 20), "{shortest_path:?}");
+        assert!(shortest_path.iter().sum::<u32>() > greedy.iter().sum::<u32>());
+    }
+
+    #[test]
+    fn hamiltonian_fills_most_of_the_board() {
+        // 13x9 cells inside the walls
+        let states = soak(ControllerKind::Hamiltonian, 10_000);
+        for state in &states {
+            let snake = &state.snakes[0];
+            assert!(snake.score.n_apples >= 117 / 2, "seed {}", state.seed);
+            // It only ever dies in the endgame
+            assert!(
+                snake.alive || snake.body.len() >= 117 * 3 / 4,
+                "seed {}",
+                state.seed
+            );
+        }
+    }
+}
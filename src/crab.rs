@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    game::{Direction, GameState},
+    geometry,
+    grid::{Cell, Grid},
+    snake::MoveEvent,
+};
+
+/// A crab of the simulation. Crabs only walk sideways, perpendicular to where
+/// they face, and have to turn to walk along the other axis.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crab {
+    pub cell: IVec2,
+    pub facing: Direction,
+}
+
+/// What a crab does on its turn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CrabMove {
+    Step(Direction),
+    Turn(Direction),
+    Stay,
+}
+
+impl Crab {
+    /// The directions the crab can walk in.
+    pub fn sideways(&self) -> [Direction; 2] {
+        match self.facing {
+            Direction::Up | Direction::Down => [Direction::Left, Direction::Right],
+            Direction::Left | Direction::Right => [Direction::Up, Direction::Down],
+        }
+    }
+
+    /// Whether a head moving in `direction` onto the crab comes from behind it.
+    pub fn is_behind(&self, direction: Direction) -> bool {
+        direction == self.facing
+    }
+
+    /// Walks sideways towards the target, or turns if the target lies straight
+    /// ahead or behind. It then faces the target, so it can only be eaten by a
+    /// snake sneaking up on it. Without a target the crab wanders randomly.
+    /// `open` holds the sideways directions the crab may step in.
+    pub fn next_move(
+        &self,
+        target: Option<IVec2>,
+        open: &[Direction],
+        rng: &mut impl Rng,
+    ) -> CrabMove {
+        let sideways = self.sideways();
+        if let Some(target) = target {
+            let delta = target - self.cell;
+            let towards = |direction: &Direction| delta.dot(direction.delta()) > 0;
+
+            if let Some(direction) = open.iter().find(|d| towards(d)) {
+                return CrabMove::Step(*direction);
+            }
+            if delta.dot(self.facing.delta()) != 0 {
+                let facing = match sideways.iter().find(|d| towards(d)) {
+                    Some(direction) => *direction,
+                    None => sideways[rng.gen_range(0..2)],
+                };
+                return CrabMove::Turn(facing);
+            }
+        }
+
+        match open {
+            [] => CrabMove::Stay,
+            _ => CrabMove::Step(open[rng.gen_range(0..open.len())]),
+        }
+    }
+}
+
+/// The index of the crab in `GameState::crabs`.
+#[derive(Component)]
+pub struct CrabId(pub usize);
+
+/// Marks the front of a crab, a child of the crab entity.
+#[derive(Component)]
+pub struct CrabEyes;
+
+fn eyes_transform(facing: Direction) -> Transform {
+    // Relative to the unit square of the crab
+    let offset = facing.delta().as_vec2() * 0.3;
+    Transform::from_translation(offset.extend(0.1)).with_scale(Vec3::new(0.35, 0.35, 1.))
+}
+
+pub fn spawn_crabs(
+    grid: Res<Grid>,
+    state: Res<GameState>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (i, crab) in state.crabs.iter().enumerate() {
+        let cell = grid.get_cell(crab.cell);
+        let square = geometry::get_colored_square(
+            grid.lambda,
+            cell.pos_x,
+            cell.pos_y,
+            Color::rgb(9.4, 2.0, 1.0),
+            &mut meshes,
+            &mut materials,
+        );
+        let mut eyes = geometry::get_colored_square(
+            1.,
+            0.,
+            0.,
+            Color::rgb(0.1, 0.1, 0.1),
+            &mut meshes,
+            &mut materials,
+        );
+        eyes.transform = eyes_transform(crab.facing);
+
+        commands
+            .spawn((square, cell, CrabId(i)))
+            .with_children(|parent| {
+                parent.spawn((eyes, CrabEyes));
+            });
+    }
+}
+
+pub fn despawn_crabs(mut commands: Commands, crabs: Query<Entity, With<CrabId>>) {
+    for entity in &crabs {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Mirrors the cells and facings of the simulated crabs onto the crab entities.
+/// Crabs that could not respawn for lack of space are despawned.
+pub fn move_crabs(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    state: Res<GameState>,
+    mut ev_move: EventReader<MoveEvent>,
+    mut crabs: Query<(Entity, &mut Transform, &mut Cell, &CrabId, &Children), Without<CrabEyes>>,
+    mut eyes: Query<&mut Transform, With<CrabEyes>>,
+) {
+    if ev_move.read().last().is_none() {
+        return;
+    }
+
+    for (entity, mut t, mut c, CrabId(i), children) in &mut crabs {
+        let Some(crab) = state.crabs.get(*i) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        c.set(&grid.get_cell(crab.cell));
+        t.translation.x = c.pos_x;
+        t.translation.y = c.pos_y;
+        for child in children {
+            if let Ok(mut eyes) = eyes.get_mut(*child) {
+                *eyes = eyes_transform(crab.facing);
+            }
+        }
+    }
+}
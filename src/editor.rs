@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    game::Direction,
+    geometry,
+    grid::{Cell, Grid},
+    level::Level,
+    AppState, Config,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditorTool {
+    Wall,
+    Spawn,
+    AppleZone,
+}
+
+#[derive(Resource)]
+pub struct Editor {
+    pub tool: EditorTool,
+    pub file: PathBuf,
+    /// Result of the last save, shown below the controls.
+    pub message: String,
+}
+
+/// Shows the spawn point and the apple zone, which have no entities while editing.
+#[derive(Component)]
+pub struct EditorMarker;
+
+#[derive(Component)]
+pub struct EditorHud;
+
+pub fn enter_editor(mut commands: Commands, config: Res<Config>) {
+    let file = config
+        .level
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("levels/custom.ron"));
+
+    commands.insert_resource(Editor {
+        tool: EditorTool::Wall,
+        file,
+        message: String::new(),
+    });
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.,
+                color: Color::rgb(1.00, 0.34, 0.20),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        EditorHud,
+    ));
+}
+
+pub fn exit_editor(
+    mut commands: Commands,
+    hud: Query<Entity, With<EditorHud>>,
+    markers: Query<Entity, With<EditorMarker>>,
+) {
+    for entity in hud.iter().chain(markers.iter()) {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<Editor>();
+}
+
+fn cursor_cell(
+    window: &Window,
+    camera: &Query<(&Camera, &GlobalTransform)>,
+    grid: &Grid,
+) -> Option<Cell> {
+    let (camera, camera_transform) = camera.get_single().ok()?;
+    let cursor = window.cursor_position()?;
+    let pos = camera.viewport_to_world_2d(camera_transform, cursor)?;
+    Some(grid.get_cell_from_position(pos.x, pos.y))
+}
+
+/// Left click applies the current tool to the cell below the cursor, right click clears it.
+pub fn edit_cells(
+    editor: Res<Editor>,
+    grid: Res<Grid>,
+    mut level: ResMut<Level>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    window: Query<&Window>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    let place = mouse.just_pressed(MouseButton::Left);
+    let clear = mouse.just_pressed(MouseButton::Right);
+    if !place && !clear {
+        return;
+    }
+
+    let Some(cell) = cursor_cell(window.single(), &camera, &grid) else {
+        return;
+    };
+    let idx = IVec2::new(cell.idx_x, cell.idx_y);
+    if !level.board.contains(idx) {
+        return;
+    }
+
+    if clear {
+        level.walls.remove(&idx);
+        level.apple_zone.remove(&idx);
+        return;
+    }
+
+    match editor.tool {
+        EditorTool::Wall => {
+            if !level.walls.remove(&idx) && idx != level.spawn {
+                level.walls.insert(idx);
+                level.apple_zone.remove(&idx);
+            }
+        }
+        EditorTool::Spawn => {
+            level.spawn = idx;
+            level.walls.remove(&idx);
+        }
+        EditorTool::AppleZone => {
+            if !level.apple_zone.remove(&idx) {
+                level.apple_zone.insert(idx);
+                level.walls.remove(&idx);
+            }
+        }
+    }
+}
+
+pub fn editor_keys(
+    keycode: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+    mut editor: ResMut<Editor>,
+    mut level: ResMut<Level>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for key in keycode.get_just_pressed() {
+        match key {
+            KeyCode::Digit1 => editor.tool = EditorTool::Wall,
+            KeyCode::Digit2 => editor.tool = EditorTool::Spawn,
+            KeyCode::Digit3 => editor.tool = EditorTool::AppleZone,
+            KeyCode::ArrowUp => level.direction = Direction::Up,
+            KeyCode::ArrowDown => level.direction = Direction::Down,
+            KeyCode::ArrowLeft => level.direction = Direction::Left,
+            KeyCode::ArrowRight => level.direction = Direction::Right,
+            KeyCode::KeyS => {
+                editor.message = match level.check(&config).and_then(|_| level.save(&editor.file)) {
+                    Ok(()) => format!("Saved to {}", editor.file.display()),
+                    Err(err) => format!("Not saved: {err}"),
+                };
+            }
+            KeyCode::Escape => match level.check(&config) {
+                // The game can only start from a level the snake and the apples fit into
+                Ok(()) => next_state.set(AppState::Menu),
+                Err(err) => editor.message = format!("Cannot leave the editor: {err}"),
+            },
+            _ => {}
+        }
+    }
+}
+
+pub fn render_hud(
+    editor: Res<Editor>,
+    level: Res<Level>,
+    mut hud: Query<&mut Text, With<EditorHud>>,
+) {
+    let mut text = hud.single_mut();
+    text.sections[0].value = format!(
+        "Level editor - {}\n\
+         1: walls | 2: spawn | 3: apple zone | arrows: direction ({:?}) | S: save | Escape: menu\n\
+         Tool: {:?} | Left click: apply | Right click: clear\n\
+         {}",
+        editor.file.display(),
+        level.direction,
+        editor.tool,
+        editor.message
+    );
+}
+
+/// Replaces the spawn and apple zone markers after the level was edited.
+pub fn respawn_markers(
+    mut commands: Commands,
+    config: Res<Config>,
+    grid: Res<Grid>,
+    level: Res<Level>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    markers: Query<Entity, With<EditorMarker>>,
+) {
+    for entity in &markers {
+        commands.entity(entity).despawn();
+    }
+
+    let spawn = (0..config.n_players as usize)
+        .map(|player| (level.spawn_of(player).0, Color::rgb(9.4, 1.0, 0.5)));
+    let zone = level
+        .apple_zone
+        .iter()
+        .map(|idx| (*idx, Color::rgb(1.0, 4.0, 1.0)));
+    for (idx, color) in spawn.chain(zone) {
+        let cell = grid.get_cell(idx);
+        let square = geometry::get_colored_square(
+            grid.lambda,
+            cell.pos_x,
+            cell.pos_y,
+            color,
+            &mut meshes,
+            &mut materials,
+        );
+        commands.spawn((square, EditorMarker, cell));
+    }
+}
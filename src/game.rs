@@ -0,0 +1,1497 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::{component::Component, system::Resource},
+    math::IVec2,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crab::{Crab, CrabMove},
+    level::Level,
+    occupancy::Occupancy,
+    score::Score,
+    BoundaryMode, Config, CrabBehavior, WinCondition,
+};
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AppleKind {
+    Normal,
+    /// Scores `golden_score_factor` times as much as a normal apple.
+    Golden,
+    /// Removes `poison_shrink` segments instead of growing the snake.
+    Poison,
+    SlowDown,
+    SpeedUp,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PlacedApple {
+    pub cell: IVec2,
+    pub kind: AppleKind,
+    /// Ticks until the apple expires, `None` if apples live forever.
+    pub ticks_left: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn delta(&self) -> IVec2 {
+        match self {
+            Direction::Up => IVec2::new(0, 1),
+            Direction::Down => IVec2::new(0, -1),
+            Direction::Left => IVec2::new(-1, 0),
+            Direction::Right => IVec2::new(1, 0),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+/// Logical extent of the playing ground, independent of any screen resolution.
+/// It spans the cells with index `-max_idx_x..=max_idx_x` and `-max_idx_y..=max_idx_y`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Board {
+    pub max_idx_x: i32,
+    pub max_idx_y: i32,
+}
+
+impl Board {
+    /// The cell counts are expected to be uneven, see `Config::validate`.
+    pub fn new(n_cells_x: u32, n_cells_y: u32) -> Board {
+        Board {
+            max_idx_x: (n_cells_x as i32 - 1) / 2,
+            max_idx_y: (n_cells_y as i32 - 1) / 2,
+        }
+    }
+
+    pub fn contains(&self, cell: IVec2) -> bool {
+        cell.x.abs() <= self.max_idx_x && cell.y.abs() <= self.max_idx_y
+    }
+
+    pub fn n_cells(&self) -> usize {
+        ((2 * self.max_idx_x + 1) * (2 * self.max_idx_y + 1)) as usize
+    }
+
+    /// Row-major index of the cell, `None` if it is not on the board.
+    pub fn index(&self, cell: IVec2) -> Option<usize> {
+        if !self.contains(cell) {
+            return None;
+        }
+
+        let width = 2 * self.max_idx_x + 1;
+        let x = cell.x + self.max_idx_x;
+        let y = cell.y + self.max_idx_y;
+        Some((x + y * width) as usize)
+    }
+
+    /// Maps a cell just outside the board onto the opposite edge.
+    pub fn wrap(&self, cell: IVec2) -> IVec2 {
+        let width = 2 * self.max_idx_x + 1;
+        let height = 2 * self.max_idx_y + 1;
+        IVec2::new(
+            (cell.x + self.max_idx_x).rem_euclid(width) - self.max_idx_x,
+            (cell.y + self.max_idx_y).rem_euclid(height) - self.max_idx_y,
+        )
+    }
+
+    /// The cell next to `cell` in the direction. Leaving the board wraps
+    /// around to the opposite edge if the boundary lets it.
+    pub fn neighbor(&self, cell: IVec2, direction: Direction, boundary: BoundaryMode) -> IVec2 {
+        match boundary {
+            BoundaryMode::Solid => cell + direction.delta(),
+            BoundaryMode::Wrap => self.wrap(cell + direction.delta()),
+        }
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = IVec2> {
+        let (max_x, max_y) = (self.max_idx_x, self.max_idx_y);
+        (-max_y..=max_y).flat_map(move |y| (-max_x..=max_x).map(move |x| IVec2::new(x, y)))
+    }
+
+    /// The rectangular border of the board.
+    pub fn get_wall(&self) -> Vec<IVec2> {
+        let mut wall = Vec::with_capacity((4 * self.max_idx_x + 4 * self.max_idx_y) as usize);
+
+        // left and right cols
+        for idx_x in [-self.max_idx_x, self.max_idx_x] {
+            for idx_y in -self.max_idx_y..=self.max_idx_y {
+                wall.push(IVec2::new(idx_x, idx_y));
+            }
+        }
+
+        // top and bot rows
+        for idx_y in [-self.max_idx_y, self.max_idx_y] {
+            for idx_x in (-self.max_idx_x + 1)..self.max_idx_x {
+                wall.push(IVec2::new(idx_x, idx_y));
+            }
+        }
+
+        wall
+    }
+}
+
+/// Slowest speed apples can bring the snakes down to, in blocks per second.
+pub const MIN_SPEED: f32 = 2.;
+
+/// Number of cells between `a` and `b` along the axes, ignoring walls.
+pub(crate) fn distance(a: IVec2, b: IVec2) -> i32 {
+    let delta = (a - b).abs();
+    delta.x + delta.y
+}
+
+/// Everything that happened during a single call to [`GameState::step`].
+/// `snake` is the index into `GameState::snakes`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    Moved {
+        snake: usize,
+        from: IVec2,
+        to: IVec2,
+    },
+    /// `apple` is the index into `GameState::apples`.
+    AppleEaten {
+        snake: usize,
+        apple: usize,
+        cell: IVec2,
+        kind: AppleKind,
+    },
+    AppleRelocated {
+        apple: usize,
+        cell: IVec2,
+        kind: AppleKind,
+    },
+    /// The apple was not eaten within its lifetime, it is relocated right after.
+    AppleExpired {
+        apple: usize,
+        cell: IVec2,
+    },
+    Shrunk {
+        snake: usize,
+        length: usize,
+    },
+    ScoreIncreased {
+        snake: usize,
+    },
+    /// The head hit a wall, a snake or the front of a crab, the body stays
+    /// on the board as an obstacle.
+    Crashed {
+        snake: usize,
+    },
+    /// `crab` is the index into `GameState::crabs`.
+    CrabMoved {
+        crab: usize,
+        from: IVec2,
+        to: IVec2,
+    },
+    /// The snake got the crab from behind, the crab respawns elsewhere.
+    CrabEaten {
+        snake: usize,
+        crab: usize,
+        cell: IVec2,
+    },
+    /// A crab walked onto the apple, it is relocated right after.
+    AppleStolen {
+        crab: usize,
+        apple: usize,
+        cell: IVec2,
+    },
+    SpeedChanged {
+        speed: f32,
+    },
+    /// The win condition of the config is met, or the board is full.
+    LevelComplete,
+    GameOver,
+}
+
+/// A snake controlled by one player.
+#[derive(Clone, Debug)]
+pub struct Snake {
+    /// Cells, head first.
+    pub body: VecDeque<IVec2>,
+    pub direction: Direction,
+    pub score: Score,
+    /// Cleared when the snake crashed.
+    pub alive: bool,
+    pending_growth: u32,
+    /// Apples eaten in quick succession, see `ScoringRules::combo_ticks`.
+    combo: u32,
+    /// Tick of the last apple that scored.
+    last_apple: Option<u32>,
+}
+
+impl Snake {
+    pub fn head(&self) -> IVec2 {
+        self.body[0]
+    }
+
+    /// Segments the snake still grows by from apples and crabs it ate.
+    pub fn pending_growth(&self) -> u32 {
+        self.pending_growth
+    }
+
+    /// Length of the current combo of apples, 0 before the first apple.
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    /// Only turns by 90 degrees are accepted, the snake cannot reverse into itself.
+    /// Returns whether the snake turned.
+    fn steer(&mut self, direction: Direction) -> bool {
+        let turns = direction != self.direction && direction != self.direction.opposite();
+        if turns {
+            self.direction = direction;
+        }
+        turns
+    }
+}
+
+/// A direction given to a snake: the tick, the index of the snake and the direction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TickInput(pub u32, pub usize, pub Direction);
+
+/// Headless snake simulation. The Bevy systems only drive `step` and mirror
+/// the resulting state on screen, so whole games can be played without a window.
+#[derive(Resource)]
+pub struct GameState {
+    pub level: Level,
+    /// One snake per player, all of them move in the same tick.
+    pub snakes: Vec<Snake>,
+    /// `None` while the apple zone has no free cell, the apple is placed as
+    /// soon as one frees up.
+    pub apples: Vec<Option<PlacedApple>>,
+    pub crabs: Vec<Crab>,
+    /// Cells of the apple zone neither taken by a snake, an apple nor a crab.
+    pub occupancy: Occupancy,
+    /// Apples that expired before any snake could eat them.
+    pub n_missed: u32,
+    /// Speed in blocks per second.
+    pub speed: f32,
+    /// Seconds of play, every tick lasts `1 / speed`.
+    pub elapsed: f32,
+    /// Set when the game ended, `won` tells whether the level was completed.
+    pub game_over: bool,
+    pub won: bool,
+    /// With several players the one who won the game, `None` for a draw.
+    pub winner: Option<usize>,
+    /// Seed of the random apples and crabs.
+    pub seed: u64,
+    /// Every turn of the snakes so far, together with the seed enough to play
+    /// the game again.
+    pub inputs: Vec<TickInput>,
+    config: Config,
+    ticks: u32,
+    rng: StdRng,
+}
+
+impl GameState {
+    /// The level is expected to fit the config, see `Level::check`.
+    pub fn new(config: &Config, level: &Level, seed: u64) -> GameState {
+        let mut occupancy = Occupancy::new(level);
+        let snakes: Vec<_> = (0..config.n_players as usize)
+            .map(|player| {
+                let body: VecDeque<_> = level
+                    .initial_snake(player, config.initial_bodylength)
+                    .into();
+                for cell in &body {
+                    occupancy.occupy(*cell);
+                }
+                Snake {
+                    body,
+                    direction: level.spawn_of(player).1,
+                    score: Score::default(),
+                    alive: true,
+                    pending_growth: 0,
+                    combo: 0,
+                    last_apple: None,
+                }
+            })
+            .collect();
+
+        let mut state = GameState {
+            level: level.clone(),
+            snakes,
+            apples: Vec::with_capacity(config.n_apples as usize),
+            crabs: Vec::with_capacity(config.crabs.n_crabs as usize),
+            occupancy,
+            n_missed: 0,
+            speed: config.initial_speed,
+            elapsed: 0.,
+            game_over: false,
+            won: false,
+            winner: None,
+            seed,
+            inputs: Vec::new(),
+            config: config.clone(),
+            ticks: 0,
+            rng: StdRng::seed_from_u64(seed),
+        };
+        for _ in 0..config.n_apples {
+            let apple = state
+                .place_apple()
+                .expect("the level leaves a free cell for every apple");
+            state.apples.push(Some(apple));
+        }
+        for _ in 0..config.crabs.n_crabs {
+            let crab = state
+                .place_crab()
+                .expect("the level leaves a free cell for every crab");
+            state.crabs.push(crab);
+        }
+
+        state
+    }
+
+    /// Advances every living snake by exactly one cell. `inputs` holds the
+    /// direction requested by each player since the last step, if any.
+    pub fn step(&mut self, inputs: &[Option<Direction>]) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        if self.game_over {
+            return events;
+        }
+
+        for (i, (snake, input)) in self.snakes.iter_mut().zip(inputs).enumerate() {
+            if let Some(direction) = input {
+                // Ignored turns change nothing and are left out of the record
+                if snake.steer(*direction) {
+                    self.inputs.push(TickInput(self.ticks, i, *direction));
+                }
+            }
+        }
+        self.elapsed += 1. / self.speed;
+        self.ticks += 1;
+
+        // All tails leave their cells before any head enters, a head may follow a tail
+        let mut moves = Vec::with_capacity(self.snakes.len());
+        for (i, snake) in self.snakes.iter_mut().enumerate() {
+            if !snake.alive {
+                continue;
+            }
+
+            let from = snake.head();
+            let to = self
+                .level
+                .board
+                .neighbor(from, snake.direction, self.config.boundary);
+            if snake.pending_growth > 0 {
+                snake.pending_growth -= 1;
+            } else if let Some(tail) = snake.body.pop_back() {
+                self.occupancy.release(tail);
+            }
+            moves.push((i, from, to));
+        }
+        for (snake, from, to) in moves {
+            self.snakes[snake].body.push_front(to);
+            self.occupancy.occupy(to);
+            events.push(GameEvent::Moved { snake, from, to });
+        }
+
+        // Decided before anyone is removed, so two heads meeting both crash
+        let crashed: Vec<_> = (0..self.snakes.len())
+            .filter(|i| self.snakes[*i].alive && self.snake_crashes(*i))
+            .collect();
+        for snake in crashed {
+            self.crash(snake, &mut events);
+        }
+        self.meet_crabs(&mut events);
+        if self.ticks.is_multiple_of(self.config.crabs.ticks_per_move) {
+            self.move_crabs(&mut events);
+        }
+        if self.check_survivors(&mut events) {
+            return events;
+        }
+
+        for snake in 0..self.snakes.len() {
+            if !self.snakes[snake].alive || self.game_over {
+                continue;
+            }
+
+            let head = self.snakes[snake].head();
+            if let Some(apple) = self.apple_at(head) {
+                let kind = self.apples[apple].unwrap().kind;
+                events.push(GameEvent::AppleEaten {
+                    snake,
+                    apple,
+                    cell: head,
+                    kind,
+                });
+                self.apple_eaten(snake, kind, &mut events);
+                self.relocate_apple(apple, &mut events);
+            }
+        }
+
+        self.expire_apples(&mut events);
+        self.place_waiting_apples(&mut events);
+        self.check_win_condition(&mut events);
+
+        events
+    }
+
+    fn crash(&mut self, snake: usize, events: &mut Vec<GameEvent>) {
+        self.snakes[snake].alive = false;
+        events.push(GameEvent::Crashed { snake });
+    }
+
+    /// Whether the cell is taken by any snake, living or not.
+    fn on_snake(&self, cell: IVec2) -> bool {
+        self.snakes.iter().any(|s| s.body.contains(&cell))
+    }
+
+    /// A head that moved onto a crab eats it if it came from behind, otherwise
+    /// the snake crashes.
+    fn meet_crabs(&mut self, events: &mut Vec<GameEvent>) {
+        for snake in 0..self.snakes.len() {
+            if !self.snakes[snake].alive {
+                continue;
+            }
+
+            let head = self.snakes[snake].head();
+            let Some(crab) = self.crabs.iter().position(|c| c.cell == head) else {
+                continue;
+            };
+            if self.crabs[crab].is_behind(self.snakes[snake].direction) {
+                events.push(GameEvent::CrabEaten {
+                    snake,
+                    crab,
+                    cell: head,
+                });
+                self.crab_eaten(snake, crab, events);
+            } else {
+                self.crash(snake, events);
+            }
+        }
+    }
+
+    fn crab_eaten(&mut self, snake: usize, crab: usize, events: &mut Vec<GameEvent>) {
+        let speed = self.speed;
+        let eater = &mut self.snakes[snake];
+        eater.pending_growth += self.config.n_elements_per_apple;
+        eater.score.n_crabs += 1;
+        eater.score.score +=
+            self.config.crabs.score_factor * self.config.score_increment as f32 * speed * speed;
+        events.push(GameEvent::ScoreIncreased { snake });
+
+        // The head now takes the cell, so it stays occupied
+        match self.place_crab() {
+            Some(placed) => self.crabs[crab] = placed,
+            None => {
+                self.crabs.remove(crab);
+            }
+        }
+    }
+
+    /// Whether a crab may walk onto the cell. Walls, bodies and other crabs
+    /// block it, the head of a living snake does not.
+    fn crab_can_enter(&self, cell: IVec2) -> bool {
+        let on_body = self.snakes.iter().any(|s| {
+            let skip = usize::from(s.alive);
+            s.body.iter().skip(skip).any(|c| *c == cell)
+        });
+        self.level.is_free(cell) && !on_body && !self.crabs.iter().any(|c| c.cell == cell)
+    }
+
+    fn crab_target(&self, crab: &Crab) -> Option<IVec2> {
+        let distance = |cell: &IVec2| distance(*cell, crab.cell);
+        match self.config.crabs.behavior {
+            CrabBehavior::Apples => self
+                .apples
+                .iter()
+                .flatten()
+                .map(|a| a.cell)
+                .min_by_key(distance),
+            CrabBehavior::Chase => self
+                .snakes
+                .iter()
+                .filter(|s| s.alive)
+                .map(|s| s.head())
+                .min_by_key(distance),
+        }
+    }
+
+    /// Every crab steps sideways or turns. A crab walking onto the head of a
+    /// snake kills it, a crab walking onto an apple takes it away.
+    fn move_crabs(&mut self, events: &mut Vec<GameEvent>) {
+        let (board, boundary) = (self.level.board, self.config.boundary);
+        for crab in 0..self.crabs.len() {
+            let current = self.crabs[crab];
+            let open: Vec<_> = current
+                .sideways()
+                .into_iter()
+                .filter(|d| self.crab_can_enter(board.neighbor(current.cell, *d, boundary)))
+                .collect();
+            let target = self.crab_target(&current);
+
+            match current.next_move(target, &open, &mut self.rng) {
+                CrabMove::Step(direction) => {
+                    let from = current.cell;
+                    let to = board.neighbor(from, direction, boundary);
+                    if !self.on_snake(from) {
+                        self.occupancy.release(from);
+                    }
+                    self.occupancy.occupy(to);
+                    self.crabs[crab].cell = to;
+                    events.push(GameEvent::CrabMoved { crab, from, to });
+
+                    if let Some(snake) = self.snakes.iter().position(|s| s.alive && s.head() == to)
+                    {
+                        self.crash(snake, events);
+                    }
+                    if let Some(apple) = self.apple_at(to) {
+                        events.push(GameEvent::AppleStolen {
+                            crab,
+                            apple,
+                            cell: to,
+                        });
+                        self.relocate_apple(apple, events);
+                    }
+                }
+                CrabMove::Turn(facing) => self.crabs[crab].facing = facing,
+                CrabMove::Stay => {}
+            }
+        }
+    }
+
+    /// The cell next to `cell` in the direction, across the edge on a wrapping board.
+    pub fn neighbor(&self, cell: IVec2, direction: Direction) -> IVec2 {
+        self.level
+            .board
+            .neighbor(cell, direction, self.config.boundary)
+    }
+
+    /// Whether a head may move onto the cell in the next step without crashing.
+    /// The tail of a living snake that does not grow is left before any head
+    /// moves, crabs count as unsafe, even from behind.
+    pub fn is_safe(&self, cell: IVec2) -> bool {
+        let blocked = self.snakes.iter().any(|s| {
+            let leaves_tail = s.alive && s.pending_growth == 0 && s.body.len() > 1;
+            let n_blocking = s.body.len() - usize::from(leaves_tail);
+            s.body.iter().take(n_blocking).any(|c| *c == cell)
+        });
+        self.level.is_free(cell) && !blocked && !self.crabs.iter().any(|c| c.cell == cell)
+    }
+
+    /// Index of the apple on the cell.
+    fn apple_at(&self, cell: IVec2) -> Option<usize> {
+        self.apples
+            .iter()
+            .position(|a| a.is_some_and(|a| a.cell == cell))
+    }
+
+    /// Per cell of the board in row-major order, whether a snake, an apple or
+    /// a crab is on it.
+    fn taken_cells(&self) -> Vec<bool> {
+        let board = self.level.board;
+        let mut taken = vec![false; board.n_cells()];
+        let cells = self.snakes.iter().flat_map(|s| s.body.iter().copied());
+        let cells = cells
+            .chain(self.apples.iter().flatten().map(|a| a.cell))
+            .chain(self.crabs.iter().map(|c| c.cell));
+        for cell in cells {
+            if let Some(index) = board.index(cell) {
+                taken[index] = true;
+            }
+        }
+        taken
+    }
+
+    /// Whether every free cell of the level is taken by a snake, an apple or
+    /// a crab. The apple zone may be full long before.
+    fn is_board_full(&self) -> bool {
+        let board = self.level.board;
+        let taken = self.taken_cells();
+        board
+            .cells()
+            .all(|cell| !self.level.is_free(cell) || taken[board.index(cell).unwrap()])
+    }
+
+    /// Number of steps taken so far.
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Ends the game once a single player has no snake left, or once only
+    /// one snake of several is left, which wins.
+    fn check_survivors(&mut self, events: &mut Vec<GameEvent>) -> bool {
+        let alive: Vec<_> = (0..self.snakes.len())
+            .filter(|i| self.snakes[*i].alive)
+            .collect();
+        let over = match self.snakes.len() {
+            1 => alive.is_empty(),
+            _ => alive.len() <= 1,
+        };
+        if over {
+            self.game_over = true;
+            self.winner = alive.first().copied();
+            events.push(GameEvent::GameOver);
+        }
+
+        over
+    }
+
+    fn check_win_condition(&mut self, events: &mut Vec<GameEvent>) {
+        if self.game_over {
+            return;
+        }
+
+        let reached = |snake: &&Snake| match self.config.win_condition {
+            WinCondition::FillBoard => false,
+            WinCondition::Apples(n_apples) => snake.score.n_apples >= n_apples,
+            WinCondition::Score { target, .. } => snake.score.score >= target,
+        };
+        if let Some(winner) = self.snakes.iter().position(|s| reached(&s)) {
+            self.winner = Some(winner);
+            self.complete_level(events);
+            return;
+        }
+
+        if let WinCondition::Score { time_limit, .. } = self.config.win_condition {
+            if self.elapsed > time_limit {
+                self.game_over = true;
+                self.winner = self.leader();
+                events.push(GameEvent::GameOver);
+            }
+        }
+    }
+
+    fn complete_level(&mut self, events: &mut Vec<GameEvent>) {
+        self.game_over = true;
+        self.won = true;
+        self.winner = self.winner.or_else(|| self.leader());
+
+        if let (Some(winner), Some(bonus)) = (self.winner, self.config.scoring.time_bonus) {
+            let seconds_left = (bonus.par_time - self.elapsed).max(0.);
+            self.snakes[winner].score.score += bonus.points_per_second * seconds_left;
+            events.push(GameEvent::ScoreIncreased { snake: winner });
+        }
+        events.push(GameEvent::LevelComplete);
+    }
+
+    /// The player with the strictly highest score, `None` on a tie.
+    fn leader(&self) -> Option<usize> {
+        let best = self
+            .snakes
+            .iter()
+            .map(|s| s.score.score)
+            .fold(f32::MIN, f32::max);
+        let mut leaders = (0..self.snakes.len()).filter(|i| self.snakes[*i].score.score == best);
+        match (leaders.next(), leaders.next()) {
+            (Some(leader), None) => Some(leader),
+            _ => None,
+        }
+    }
+
+    /// Whether the head of the snake is on a wall or on any snake, its own
+    /// head excluded. Two heads on the same cell crash into each other.
+    pub fn snake_crashes(&self, snake: usize) -> bool {
+        let head = self.snakes[snake].head();
+        if !self.level.is_free(head) {
+            return true;
+        }
+
+        self.snakes.iter().enumerate().any(|(i, other)| {
+            let skip = usize::from(i == snake);
+            other.body.iter().skip(skip).any(|c| *c == head)
+        })
+    }
+
+    fn apple_eaten(&mut self, snake: usize, kind: AppleKind, events: &mut Vec<GameEvent>) {
+        let kinds = &self.config.apple_kinds;
+        let growth = self.config.n_elements_per_apple;
+        match kind {
+            AppleKind::Normal => {
+                self.snakes[snake].pending_growth += growth;
+                self.increment_score(snake, 1., events);
+            }
+            AppleKind::Golden => {
+                self.snakes[snake].pending_growth += growth;
+                self.increment_score(snake, kinds.golden_score_factor, events);
+            }
+            AppleKind::Poison => {
+                let shrink = kinds.poison_shrink as usize;
+                let body = &mut self.snakes[snake];
+                body.pending_growth = 0;
+                body.combo = 0;
+                body.last_apple = None;
+                let length = body.body.len().saturating_sub(shrink).max(1);
+                while body.body.len() > length {
+                    let tail = body.body.pop_back().unwrap();
+                    self.occupancy.release(tail);
+                }
+                events.push(GameEvent::Shrunk { snake, length });
+            }
+            AppleKind::SlowDown => {
+                let factor = kinds.speed_factor;
+                self.snakes[snake].pending_growth += growth;
+                self.increment_score(snake, 1., events);
+                self.set_speed(self.speed / factor, events);
+            }
+            AppleKind::SpeedUp => {
+                let factor = kinds.speed_factor;
+                self.snakes[snake].pending_growth += growth;
+                self.increment_score(snake, 1., events);
+                self.set_speed(self.speed * factor, events);
+            }
+        }
+    }
+
+    /// Scores an apple worth `factor` normal apples and speeds up along the curve.
+    fn increment_score(&mut self, snake: usize, factor: f32, events: &mut Vec<GameEvent>) {
+        let rules = &self.config.scoring;
+        let score_increment = self.config.score_increment;
+        let speed = self.speed;
+        let ticks = self.ticks;
+        let eater = &mut self.snakes[snake];
+
+        let in_combo = rules.combo_ticks > 0
+            && eater
+                .last_apple
+                .is_some_and(|last| ticks - last <= rules.combo_ticks);
+        eater.combo = if in_combo { eater.combo + 1 } else { 1 };
+        eater.last_apple = Some(ticks);
+        let multiplier =
+            (1. + rules.combo_step * (eater.combo - 1) as f32).min(rules.max_combo_multiplier);
+
+        let length = eater.body.len() as f32;
+        let score = &mut eater.score;
+        score.n_apples += score_increment;
+        score.score += multiplier * factor * score_increment as f32 * speed * speed
+            + rules.length_bonus * length;
+        let n_eaten = score.n_apples / score_increment;
+        events.push(GameEvent::ScoreIncreased { snake });
+
+        let next_speed = self.config.speed_curve.next_speed(speed, n_eaten);
+        if next_speed != speed {
+            self.set_speed(next_speed, events);
+        }
+    }
+
+    /// Changes the speed, but never above `max_speed` and never below
+    /// `MIN_SPEED`, or the initial speed if that is slower.
+    fn set_speed(&mut self, speed: f32, events: &mut Vec<GameEvent>) {
+        let speed = self.config.max_speed.map_or(speed, |max| speed.min(max));
+        let speed = speed.max(MIN_SPEED.min(self.config.initial_speed));
+        if speed != self.speed {
+            self.speed = speed;
+            events.push(GameEvent::SpeedChanged { speed });
+        }
+    }
+
+    /// Puts an apple on a uniformly random free cell, `None` if the board is full.
+    fn place_apple(&mut self) -> Option<PlacedApple> {
+        let cell = self.occupancy.sample(&mut self.rng)?;
+        self.occupancy.occupy(cell);
+        let kind = self.roll_apple_kind();
+        Some(PlacedApple {
+            cell,
+            kind,
+            ticks_left: self.config.apple_lifetime,
+        })
+    }
+
+    /// Puts a crab with a random facing on a free cell of the level, also
+    /// outside the apple zone, `None` if the board is full. Cells right in
+    /// front of a head are avoided if possible.
+    fn place_crab(&mut self) -> Option<Crab> {
+        let board = self.level.board;
+        let taken = self.taken_cells();
+        let free: Vec<_> = board
+            .cells()
+            .filter(|cell| self.level.is_free(*cell) && !taken[board.index(*cell).unwrap()])
+            .collect();
+        let far: Vec<_> = free
+            .iter()
+            .copied()
+            .filter(|cell| self.snakes.iter().all(|s| distance(*cell, s.head()) >= 5))
+            .collect();
+        let cells = if far.is_empty() { free } else { far };
+        if cells.is_empty() {
+            return None;
+        }
+        let cell = cells[self.rng.gen_range(0..cells.len())];
+        self.occupancy.occupy(cell);
+
+        let facing = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ][self.rng.gen_range(0..4)];
+        Some(Crab { cell, facing })
+    }
+
+    /// Moves the apple to a free cell of the apple zone. If there is none it
+    /// waits for one, unless the whole board is full, which completes the level.
+    fn relocate_apple(&mut self, apple: usize, events: &mut Vec<GameEvent>) {
+        match self.place_apple() {
+            Some(placed) => {
+                self.apples[apple] = Some(placed);
+                events.push(GameEvent::AppleRelocated {
+                    apple,
+                    cell: placed.cell,
+                    kind: placed.kind,
+                });
+            }
+            None => {
+                self.apples[apple] = None;
+                if self.is_board_full() {
+                    self.complete_level(events);
+                }
+            }
+        }
+    }
+
+    /// Retries the apples that found no free cell when they were relocated.
+    fn place_waiting_apples(&mut self, events: &mut Vec<GameEvent>) {
+        for apple in 0..self.apples.len() {
+            if self.apples[apple].is_none() && !self.game_over {
+                self.relocate_apple(apple, events);
+            }
+        }
+    }
+
+    fn expire_apples(&mut self, events: &mut Vec<GameEvent>) {
+        for apple in 0..self.apples.len() {
+            if self.game_over {
+                return;
+            }
+
+            let Some(placed) = self.apples[apple].as_mut() else {
+                continue;
+            };
+            let Some(ticks_left) = placed.ticks_left.as_mut() else {
+                continue;
+            };
+            *ticks_left = ticks_left.saturating_sub(1);
+            if *ticks_left > 0 {
+                continue;
+            }
+
+            let cell = placed.cell;
+            events.push(GameEvent::AppleExpired { apple, cell });
+            self.n_missed += 1;
+
+            self.occupancy.release(cell);
+            self.relocate_apple(apple, events);
+        }
+    }
+
+    fn roll_apple_kind(&mut self) -> AppleKind {
+        let kinds = &self.config.apple_kinds;
+        let mut roll: f32 = self.rng.gen();
+        for (kind, probability) in [
+            (AppleKind::Golden, kinds.golden_probability),
+            (AppleKind::Poison, kinds.poison_probability),
+            (AppleKind::SlowDown, kinds.slow_down_probability),
+            (AppleKind::SpeedUp, kinds.speed_up_probability),
+        ] {
+            if roll < probability {
+                return kind;
+            }
+            roll -= probability;
+        }
+        AppleKind::Normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{level::LevelFile, AppleKindConfig, ConfigError, SpeedCurve, TimeBonus};
+
+    /// A single snake of length `length` in the center of the open 45x25
+    /// board, heading right. The apple is moved out of the way.
+    fn new_state(length: u32) -> GameState {
+        let config = Config {
+            initial_bodylength: length,
+            ..Default::default()
+        };
+        let level = Level::open(&config);
+        let mut state = GameState::new(&config, &level, 0);
+        put_apple(&mut state, IVec2::new(20, 10));
+        state
+    }
+
+    fn put_apple(state: &mut GameState, cell: IVec2) {
+        if let Some(apple) = state.apples[0] {
+            state.occupancy.release(apple.cell);
+        }
+        state.occupancy.occupy(cell);
+        state.apples[0] = Some(PlacedApple {
+            cell,
+            kind: AppleKind::Normal,
+            ticks_left: None,
+        });
+    }
+
+    /// Adds a crab, which only moves if `ticks_per_move` is lowered.
+    fn put_crab(state: &mut GameState, cell: IVec2, facing: Direction) {
+        state.config.crabs.ticks_per_move = 1000;
+        state.occupancy.occupy(cell);
+        state.crabs.push(Crab { cell, facing });
+    }
+
+    /// Two snakes on the open 45x25 board with the given bodies, head first.
+    fn two_snakes(bodies: [(&[[i32; 2]], Direction); 2]) -> GameState {
+        let config = Config {
+            n_players: 2,
+            ..Default::default()
+        };
+        let level = Level::open(&config);
+        let mut state = GameState::new(&config, &level, 0);
+        put_apple(&mut state, IVec2::new(20, 10));
+        for (snake, (body, direction)) in state.snakes.iter_mut().zip(bodies) {
+            for cell in &snake.body {
+                state.occupancy.release(*cell);
+            }
+            snake.body = body.iter().map(|c| IVec2::from_array(*c)).collect();
+            snake.direction = direction;
+            for cell in &snake.body {
+                state.occupancy.occupy(*cell);
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn moves_one_cell_per_step() {
+        let mut state = new_state(3);
+        let events = state.step(&[None]);
+
+        assert_eq!(state.snakes[0].head(), IVec2::new(1, 0));
+        assert_eq!(
+            Vec::from(state.snakes[0].body.clone()),
+            [IVec2::new(1, 0), IVec2::new(0, 0), IVec2::new(-1, 0)]
+        );
+        assert_eq!(
+            events,
+            [GameEvent::Moved {
+                snake: 0,
+                from: IVec2::ZERO,
+                to: IVec2::new(1, 0),
+            }]
+        );
+
+        state.step(&[Some(Direction::Up)]);
+        assert_eq!(state.snakes[0].head(), IVec2::new(1, 1));
+        assert_eq!(state.ticks(), 2);
+    }
+
+    #[test]
+    fn cannot_reverse() {
+        let mut state = new_state(3);
+        state.step(&[Some(Direction::Left)]);
+
+        assert_eq!(state.snakes[0].head(), IVec2::new(1, 0));
+        assert!(state.snakes[0].alive);
+        assert!(state.inputs.is_empty());
+    }
+
+    #[test]
+    fn crashes_into_wall() {
+        let mut state = new_state(3);
+        let max_idx_x = state.level.board.max_idx_x;
+        for _ in 1..max_idx_x {
+            state.step(&[None]);
+        }
+        assert!(!state.game_over);
+
+        let events = state.step(&[None]);
+        assert_eq!(state.snakes[0].head(), IVec2::new(max_idx_x, 0));
+        assert!(!state.snakes[0].alive);
+        assert!(events.contains(&GameEvent::Crashed { snake: 0 }));
+    }
+
+    #[test]
+    fn crashes_into_itself() {
+        let mut state = new_state(5);
+        for direction in [Direction::Up, Direction::Left] {
+            state.step(&[Some(direction)]);
+            assert!(state.snakes[0].alive);
+        }
+
+        let events = state.step(&[Some(Direction::Down)]);
+        assert!(!state.snakes[0].alive);
+        assert!(events.contains(&GameEvent::Crashed { snake: 0 }));
+        assert_eq!(events.last(), Some(&GameEvent::GameOver));
+        assert!(state.game_over);
+        assert!(!state.won);
+        assert!(state.step(&[None]).is_empty());
+    }
+
+    #[test]
+    fn wraps_around_every_edge() {
+        let config = Config {
+            initial_bodylength: 1,
+            boundary: BoundaryMode::Wrap,
+            ..Default::default()
+        };
+        let level = Level::open(&config);
+        assert!(level.walls.is_empty());
+
+        let board = level.board;
+        for (direction, max_idx) in [
+            (Direction::Right, board.max_idx_x),
+            (Direction::Left, board.max_idx_x),
+            (Direction::Up, board.max_idx_y),
+            (Direction::Down, board.max_idx_y),
+        ] {
+            let mut state = GameState::new(&config, &level, 0);
+            put_apple(&mut state, IVec2::new(20, 10));
+            state.snakes[0].direction = direction;
+            for _ in 0..max_idx {
+                state.step(&[None]);
+            }
+            assert_eq!(state.snakes[0].head(), direction.delta() * max_idx);
+
+            state.step(&[None]);
+            assert_eq!(state.snakes[0].head(), -direction.delta() * max_idx);
+            for _ in 0..max_idx {
+                state.step(&[None]);
+            }
+            assert_eq!(state.snakes[0].head(), IVec2::ZERO);
+            assert!(state.snakes[0].alive);
+        }
+    }
+
+    #[test]
+    fn head_follows_own_tail() {
+        let mut state = new_state(4);
+        for direction in [Direction::Up, Direction::Left, Direction::Down] {
+            state.step(&[Some(direction)]);
+        }
+
+        assert!(state.snakes[0].alive);
+        assert_eq!(state.snakes[0].head(), IVec2::new(-1, 0));
+        assert_eq!(state.snakes[0].body.len(), 4);
+    }
+
+    #[test]
+    fn heads_on_the_same_cell_both_crash() {
+        let mut state = two_snakes([
+            (&[[0, 0], [-1, 0], [-2, 0]], Direction::Right),
+            (&[[2, 0], [3, 0], [4, 0]], Direction::Left),
+        ]);
+        let events = state.step(&[None, None]);
+
+        assert!(events.contains(&GameEvent::Crashed { snake: 0 }));
+        assert!(events.contains(&GameEvent::Crashed { snake: 1 }));
+        assert!(state.game_over);
+        assert_eq!(state.winner, None);
+    }
+
+    #[test]
+    fn heads_cannot_swap_through_each_other() {
+        let mut state = two_snakes([
+            (&[[0, 0], [-1, 0], [-2, 0]], Direction::Right),
+            (&[[1, 0], [2, 0], [3, 0]], Direction::Left),
+        ]);
+        state.step(&[None, None]);
+
+        assert!(!state.snakes[0].alive && !state.snakes[1].alive);
+        assert!(state.game_over);
+        assert_eq!(state.winner, None);
+    }
+
+    #[test]
+    fn head_into_the_other_body_loses() {
+        let mut state = two_snakes([
+            (&[[0, 0], [0, -1], [0, -2]], Direction::Up),
+            (&[[1, 1], [0, 1], [-1, 1], [-2, 1]], Direction::Right),
+        ]);
+        let events = state.step(&[None, None]);
+
+        assert_eq!(events.last(), Some(&GameEvent::GameOver));
+        assert!(!state.snakes[0].alive);
+        assert!(state.snakes[1].alive);
+        assert_eq!(state.winner, Some(1));
+    }
+
+    #[test]
+    fn head_follows_the_other_tail() {
+        let mut state = two_snakes([
+            (&[[-1, 0], [-2, 0], [-3, 0]], Direction::Right),
+            (&[[2, 0], [1, 0], [0, 0]], Direction::Right),
+        ]);
+        state.step(&[None, None]);
+
+        assert!(state.snakes[0].alive && state.snakes[1].alive);
+        assert_eq!(state.snakes[0].head(), IVec2::ZERO);
+        assert!(!state.game_over);
+    }
+
+    #[test]
+    fn grows_after_eating() {
+        let mut state = new_state(3);
+        put_apple(&mut state, IVec2::new(1, 0));
+
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::AppleEaten {
+            snake: 0,
+            apple: 0,
+            cell: IVec2::new(1, 0),
+            kind: AppleKind::Normal,
+        }));
+        assert_eq!(state.snakes[0].score.n_apples, 1);
+        assert_eq!(state.snakes[0].pending_growth(), 1);
+        let apple = state.apples[0].unwrap();
+        assert!(!state.snakes[0].body.contains(&apple.cell));
+
+        state.step(&[None]);
+        assert_eq!(state.snakes[0].body.len(), 4);
+        assert_eq!(state.snakes[0].pending_growth(), 0);
+    }
+
+    #[test]
+    fn golden_apple_scores_more() {
+        let mut state = new_state(3);
+        put_apple(&mut state, IVec2::new(1, 0));
+        state.apples[0].as_mut().unwrap().kind = AppleKind::Golden;
+        let speed = state.speed;
+
+        state.step(&[None]);
+        let factor = state.config.apple_kinds.golden_score_factor;
+        assert_eq!(state.snakes[0].score.score, factor * speed * speed);
+        assert_eq!(state.snakes[0].pending_growth(), 1);
+    }
+
+    #[test]
+    fn poison_apple_shrinks_and_ends_the_combo() {
+        let mut state = new_state(5);
+        state.config.scoring.combo_ticks = 10;
+        state.config.n_elements_per_apple = 3;
+        put_apple(&mut state, IVec2::new(1, 0));
+        state.step(&[None]);
+        assert_eq!(state.snakes[0].combo, 1);
+        assert_eq!(state.snakes[0].pending_growth(), 3);
+
+        put_apple(&mut state, IVec2::new(2, 0));
+        state.apples[0].as_mut().unwrap().kind = AppleKind::Poison;
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::Shrunk {
+            snake: 0,
+            length: 3,
+        }));
+        assert_eq!(state.snakes[0].body.len(), 3);
+        assert_eq!(state.snakes[0].pending_growth(), 0);
+        assert_eq!(state.snakes[0].combo, 0);
+        assert_eq!(state.snakes[0].last_apple, None);
+
+        put_apple(&mut state, IVec2::new(3, 0));
+        state.apples[0].as_mut().unwrap().kind = AppleKind::Poison;
+        state.step(&[None]);
+        assert_eq!(state.snakes[0].body.len(), 1);
+        assert!(state.snakes[0].alive);
+    }
+
+    #[test]
+    fn slow_down_and_speed_up_apples_change_the_speed() {
+        let mut state = new_state(3);
+        let speed = state.speed;
+        let factor = state.config.apple_kinds.speed_factor;
+        for (x, kind) in [(1, AppleKind::SpeedUp), (2, AppleKind::SlowDown)] {
+            put_apple(&mut state, IVec2::new(x, 0));
+            state.apples[0].as_mut().unwrap().kind = kind;
+            state.step(&[None]);
+            if kind == AppleKind::SpeedUp {
+                assert_eq!(state.speed, speed * factor);
+            }
+        }
+        assert_eq!(state.speed, speed);
+
+        state.config.apple_kinds.speed_factor = 0.5;
+        assert!(matches!(
+            state.config.validate(),
+            Err(ConfigError::InvalidSpeedFactor(_))
+        ));
+    }
+
+    #[test]
+    fn completes_the_level_after_the_apples() {
+        let mut state = new_state(3);
+        state.config.win_condition = WinCondition::Apples(2);
+        put_apple(&mut state, IVec2::new(1, 0));
+        state.step(&[None]);
+        assert!(!state.game_over);
+
+        put_apple(&mut state, IVec2::new(2, 0));
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::LevelComplete));
+        assert!(state.game_over && state.won);
+        assert_eq!(state.winner, Some(0));
+    }
+
+    #[test]
+    fn ends_the_game_after_the_time_limit() {
+        let config = Config {
+            n_players: 2,
+            win_condition: WinCondition::Score {
+                target: 1000.,
+                time_limit: 1.,
+            },
+            ..Default::default()
+        };
+        let level = Level::open(&config);
+        let mut state = GameState::new(&config, &level, 0);
+        put_apple(&mut state, IVec2::new(20, 0));
+        state.snakes[1].score.score = 5.;
+
+        let mut events = Vec::new();
+        while !state.game_over {
+            assert!(state.elapsed <= 1.);
+            events = state.step(&[None, None]);
+        }
+        assert!(state.elapsed > 1.);
+        assert_eq!(events.last(), Some(&GameEvent::GameOver));
+        assert!(!state.won);
+        assert_eq!(state.winner, Some(1));
+    }
+
+    #[test]
+    fn eats_a_crab_from_behind() {
+        let mut state = new_state(3);
+        put_crab(&mut state, IVec2::new(1, 0), Direction::Right);
+        let speed = state.speed;
+
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::CrabEaten {
+            snake: 0,
+            crab: 0,
+            cell: IVec2::new(1, 0),
+        }));
+        let snake = &state.snakes[0];
+        let factor = state.config.crabs.score_factor;
+        assert!(snake.alive);
+        assert_eq!(snake.score.n_crabs, 1);
+        assert_eq!(snake.score.score, factor * speed * speed);
+        assert_eq!(snake.pending_growth(), 1);
+        assert_eq!(state.crabs.len(), 1);
+        assert!(distance(state.crabs[0].cell, snake.head()) >= 5);
+    }
+
+    #[test]
+    fn crashes_into_a_crab_from_the_front() {
+        let mut state = new_state(3);
+        put_crab(&mut state, IVec2::new(1, 0), Direction::Left);
+
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::Crashed { snake: 0 }));
+        assert!(state.game_over);
+        assert_eq!(state.snakes[0].score.n_crabs, 0);
+    }
+
+    #[test]
+    fn crab_walking_onto_a_head_kills_the_snake() {
+        let mut state = new_state(3);
+        put_crab(&mut state, IVec2::new(1, 1), Direction::Left);
+        state.config.crabs.ticks_per_move = 1;
+        state.config.crabs.behavior = CrabBehavior::Chase;
+
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::CrabMoved {
+            crab: 0,
+            from: IVec2::new(1, 1),
+            to: IVec2::new(1, 0),
+        }));
+        assert!(events.contains(&GameEvent::Crashed { snake: 0 }));
+        assert!(state.game_over);
+    }
+
+    #[test]
+    fn crab_steals_an_apple() {
+        let mut state = new_state(3);
+        put_apple(&mut state, IVec2::new(5, 5));
+        put_crab(&mut state, IVec2::new(5, 4), Direction::Left);
+        state.config.crabs.ticks_per_move = 1;
+
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::AppleStolen {
+            crab: 0,
+            apple: 0,
+            cell: IVec2::new(5, 5),
+        }));
+        assert_ne!(state.apples[0].unwrap().cell, IVec2::new(5, 5));
+        assert_eq!(state.snakes[0].score.n_apples, 0);
+        assert!(state.snakes[0].alive);
+    }
+
+    #[test]
+    fn eaten_crab_is_gone_when_the_board_is_full() {
+        let config = Config {
+            initial_bodylength: 2,
+            ..Default::default()
+        };
+        let file = LevelFile {
+            name: "Corridor".to_string(),
+            direction: Direction::Right,
+            map: ["#######", "##.S..#", "#######"].map(String::from).to_vec(),
+        };
+        let level = Level::parse(&file).unwrap();
+        level.check(&config).unwrap();
+        let mut state = GameState::new(&config, &level, 0);
+        put_apple(&mut state, IVec2::new(2, 0));
+        put_crab(&mut state, IVec2::new(1, 0), Direction::Right);
+        state.snakes[0].pending_growth = 1;
+
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::CrabEaten {
+            snake: 0,
+            crab: 0,
+            cell: IVec2::new(1, 0),
+        }));
+        assert!(state.crabs.is_empty());
+        assert!(state.snakes[0].alive);
+    }
+
+    #[test]
+    fn full_apple_zone_waits_for_a_free_cell() {
+        let config = Config {
+            initial_bodylength: 1,
+            apple_kinds: AppleKindConfig {
+                golden_probability: 0.,
+                poison_probability: 0.,
+                slow_down_probability: 0.,
+                speed_up_probability: 0.,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // A ring of 16 cells with two apple cells
+        let file = LevelFile {
+            name: "Ring".to_string(),
+            direction: Direction::Right,
+            map: [
+                "#########",
+                "#S.AA...#",
+                "#.#####.#",
+                "#.......#",
+                "#########",
+            ]
+            .map(String::from)
+            .to_vec(),
+        };
+        let level = Level::parse(&file).unwrap();
+        level.check(&config).unwrap();
+        let mut state = GameState::new(&config, &level, 0);
+
+        let mut waited = false;
+        while !state.game_over && state.ticks() < 1000 {
+            let direction = match state.snakes[0].head().to_array() {
+                [3, 1] => Some(Direction::Down),
+                [3, -1] => Some(Direction::Left),
+                [-3, -1] => Some(Direction::Up),
+                [-3, 1] => Some(Direction::Right),
+                _ => None,
+            };
+            state.step(&[direction]);
+            waited |= state.apples[0].is_none();
+            assert!(!state.game_over || state.snakes[0].body.len() == 16);
+        }
+
+        assert!(waited);
+        assert!(state.won);
+    }
+
+    #[test]
+    fn combo_multiplier_rises_up_to_its_cap() {
+        let mut state = new_state(3);
+        state.config.speed_curve = SpeedCurve::Stepwise(Vec::new());
+        state.config.scoring.combo_ticks = 2;
+        state.config.scoring.max_combo_multiplier = 1.5;
+        let points = state.speed * state.speed;
+
+        let eat = |state: &mut GameState, x| {
+            let before = state.snakes[0].score.score;
+            put_apple(state, IVec2::new(x, 0));
+            state.step(&[None]);
+            state.snakes[0].score.score - before
+        };
+        assert_eq!(eat(&mut state, 1), points);
+        assert_eq!(eat(&mut state, 2), 1.5 * points);
+        assert_eq!(eat(&mut state, 3), 1.5 * points);
+
+        // Too long after the last apple for a combo
+        put_apple(&mut state, IVec2::new(20, 10));
+        for _ in 0..3 {
+            state.step(&[None]);
+        }
+        assert_eq!(eat(&mut state, 7), points);
+    }
+
+    #[test]
+    fn length_and_time_bonus() {
+        let mut state = new_state(3);
+        state.config.scoring.length_bonus = 0.5;
+        state.config.scoring.time_bonus = Some(TimeBonus {
+            par_time: 10.,
+            points_per_second: 2.,
+        });
+        state.config.win_condition = WinCondition::Apples(1);
+        let points = state.speed * state.speed;
+        put_apple(&mut state, IVec2::new(1, 0));
+
+        state.step(&[None]);
+        assert!(state.won);
+        let length_bonus = 0.5 * 3.;
+        let time_bonus = 2. * (10. - state.elapsed);
+        assert_eq!(
+            state.snakes[0].score.score,
+            points + length_bonus + time_bonus
+        );
+    }
+
+    #[test]
+    fn only_stepwise_speed_curves_may_slow_down() {
+        let valid = |speed_curve| {
+            let config = Config {
+                speed_curve,
+                ..Default::default()
+            };
+            match config.validate() {
+                Ok(()) => true,
+                Err(ConfigError::InvalidSpeedCurve(_)) => false,
+                Err(err) => panic!("{err}"),
+            }
+        };
+        assert!(!valid(SpeedCurve::Linear {
+            every: 1,
+            step: -5.,
+        }));
+        assert!(!valid(SpeedCurve::Exponential {
+            every: 1,
+            factor: 0.9,
+        }));
+        assert!(valid(SpeedCurve::Stepwise(vec![(5, 15.), (10, 5.)])));
+        assert!(!valid(SpeedCurve::Stepwise(vec![(5, 0.)])));
+    }
+
+    #[test]
+    fn speed_stays_above_the_minimum() {
+        let mut state = new_state(3);
+        state.config.apple_kinds.speed_factor = 4.;
+        for x in 1..10 {
+            put_apple(&mut state, IVec2::new(x, 0));
+            state.apples[0].as_mut().unwrap().kind = AppleKind::SlowDown;
+            state.step(&[None]);
+        }
+        assert_eq!(state.speed, MIN_SPEED);
+        assert!(state.elapsed.is_finite());
+    }
+}
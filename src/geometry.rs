@@ -6,12 +6,32 @@ pub fn get_square(
     pos_y: f32,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<ColorMaterial>>,
+) -> MaterialMesh2dBundle<ColorMaterial> {
+    get_colored_square(
+        width,
+        pos_x,
+        pos_y,
+        Color::rgb(6.25, 9.4, 9.1),
+        meshes,
+        materials,
+    )
+}
+
+pub fn get_colored_square(
+    width: f32,
+    pos_x: f32,
+    pos_y: f32,
+    color: Color,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
 ) -> MaterialMesh2dBundle<ColorMaterial> {
     MaterialMesh2dBundle {
         // mesh: meshes.add(RegularPolygon::new(100., 4)).into(),
-        mesh: meshes.add(Rectangle::new(width, width)).into(),
-        material: materials.add(Color::rgb(6.25, 9.4, 9.1)),
-        transform: Transform::from_translation(Vec3::new(pos_x, pos_y, 0.)),
+        // Unit square scaled to the cell size, so a new layout only has to touch the transform
+        mesh: meshes.add(Rectangle::new(1., 1.)).into(),
+        material: materials.add(color),
+        transform: Transform::from_translation(Vec3::new(pos_x, pos_y, 0.))
+            .with_scale(Vec3::new(width, width, 1.)),
         ..default()
     }
 }
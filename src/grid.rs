@@ -1,8 +1,11 @@
 use bevy::{
     ecs::{component::Component, system::Resource},
     log,
+    math::IVec2,
 };
 
+use crate::game::Board;
+
 #[derive(Resource)]
 pub struct Grid {
     pub max_idx_x: i32,
@@ -36,38 +39,22 @@ impl PartialEq for Cell {
 }
 
 impl Grid {
-    pub fn new(screen_width: f32, screen_height: f32, n_cells_x: u32, n_cells_y: u32) -> Grid {
-        let n_cells_x_f32 = if n_cells_x % 2 == 0 {
-            n_cells_x as f32 + 1.
-        } else {
-            n_cells_x as f32
-        };
-
-        let n_cells_y_f32 = if n_cells_y % 2 == 0 {
-            n_cells_y as f32 + 1.
-        } else {
-            n_cells_y as f32
-        };
+    pub fn new(screen_width: f32, screen_height: f32, board: &Board) -> Grid {
+        let n_cells_x_f32 = (2 * board.max_idx_x + 1) as f32;
+        let n_cells_y_f32 = (2 * board.max_idx_y + 1) as f32;
 
         let res_x = screen_width / n_cells_x_f32;
         let res_y = screen_height / n_cells_y_f32;
-        // let res = res_x.min(res_y);
         let res = res_y.min(res_x);
 
-        // let pixels_x = res_x * n_cells_x_f32;
-        // let pixels_y = res_y * n_cells_y_f32;
-
-        let max_idx_x = ((n_cells_x_f32 - 1.) / 2.).round() as i32;
-        let max_idx_y = ((n_cells_y_f32 - 1.) / 2.).round() as i32;
-        // let max_idx_y = ((screen_height / 2. / resolution).floor() as i32) - 1;
         log::info!(
             "Grid: Width = {} ; Height = {}",
-            max_idx_x * 2 + 3,
-            max_idx_y * 2 + 3
+            board.max_idx_x * 2 + 1,
+            board.max_idx_y * 2 + 1
         );
         Grid {
-            max_idx_x,
-            max_idx_y,
+            max_idx_x: board.max_idx_x,
+            max_idx_y: board.max_idx_y,
             screen_width,
             screen_height,
             lambda: res,
@@ -100,36 +87,7 @@ impl Grid {
         }
     }
 
-    pub fn get_wall(&self) -> Vec<Cell> {
-        let mut wall =
-            Vec::with_capacity((2 * 2 * self.max_idx_x + 2 * 2 * self.max_idx_y - 4 + 4) as usize);
-
-        // top and bot rows
-        for idx_x in [-self.max_idx_x, self.max_idx_x] {
-            for idx_y in -self.max_idx_y..=self.max_idx_y {
-                wall.push(self.get_cell_from_index(idx_x, idx_y));
-            }
-        }
-
-        // left and right cols
-        for idx_y in [-self.max_idx_y, self.max_idx_y] {
-            for idx_x in (-self.max_idx_x + 1)..self.max_idx_x {
-                wall.push(self.get_cell_from_index(idx_x, idx_y));
-            }
-        }
-
-        let capacity_before = wall.capacity();
-        wall.shrink_to_fit();
-        let capacity_after = wall.capacity();
-        assert_eq!(capacity_before, capacity_after);
-
-        wall
-    }
-
-    pub fn is_inside_walls(&self, idx_x: i32, idx_y: i32) -> bool {
-        idx_x > -self.max_idx_x
-            && idx_x < self.max_idx_x
-            && idx_y > -self.max_idx_y
-            && idx_y < self.max_idx_y
+    pub fn get_cell(&self, idx: IVec2) -> Cell {
+        self.get_cell_from_index(idx.x, idx.y)
     }
 }
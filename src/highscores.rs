@@ -0,0 +1,333 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, ConfigError},
+    game::GameState,
+    grid::Grid,
+    replay::ReplayMode,
+    screens, AppState, Config, ControllerKind,
+};
+
+/// Number of entries kept per table.
+pub const TABLE_LEN: usize = 10;
+
+/// Longest name that can be entered.
+pub const MAX_NAME_LEN: usize = 12;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub n_apples: u32,
+    pub score: f32,
+    /// Day of the game as `YYYY-MM-DD`.
+    pub date: String,
+}
+
+/// The best games played with the same mode and settings, best first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Table {
+    pub mode: String,
+    /// The `Config` of the games, written without the settings that do not
+    /// change the game like the window mode.
+    pub settings: String,
+    pub entries: Vec<HighScore>,
+}
+
+/// All tables of the high-score file.
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    pub tables: Vec<Table>,
+}
+
+/// The mode of the game, scores of different modes are kept apart.
+pub fn mode(config: &Config) -> String {
+    let kinds = config.controller_kinds();
+    let n_humans = kinds
+        .iter()
+        .filter(|kind| **kind == ControllerKind::Keyboard)
+        .count();
+    let players = match (n_humans, kinds.len() - n_humans) {
+        (1, 0) => "Single player".to_string(),
+        (n_humans, 0) => format!("{n_humans} players"),
+        (n_humans, n_bots) => format!("{n_humans} against {n_bots} computer players"),
+    };
+    format!("{players}, {}", screens::difficulty_name(config))
+}
+
+fn settings(config: &Config) -> String {
+    let config = Config {
+        fullscreen: Default::default(),
+        bindings: Default::default(),
+        ..config.clone()
+    };
+    ron::to_string(&config).unwrap_or_default()
+}
+
+impl HighScores {
+    /// Reads the high scores, the tables are empty if there is no file yet.
+    pub fn load(path: &Path) -> Result<HighScores, ConfigError> {
+        if !path.exists() {
+            return Ok(HighScores::default());
+        }
+
+        let content = config::read_file(path)?;
+        ron::from_str(&content).map_err(|err| ConfigError::InvalidHighScores(err.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        config::write_ron(path, self, true)
+    }
+
+    /// The entries of games played with the config, best first.
+    pub fn entries(&self, config: &Config) -> &[HighScore] {
+        let (mode, settings) = (mode(config), settings(config));
+        self.tables
+            .iter()
+            .find(|t| t.mode == mode && t.settings == settings)
+            .map_or(&[], |t| &t.entries)
+    }
+
+    /// Whether the score makes it into the table of the config.
+    pub fn qualifies(&self, config: &Config, score: f32) -> bool {
+        let entries = self.entries(config);
+        score > 0. && (entries.len() < TABLE_LEN || entries.iter().any(|e| score > e.score))
+    }
+
+    pub fn insert(&mut self, config: &Config, entry: HighScore) {
+        let (mode, settings) = (mode(config), settings(config));
+        let table = match self
+            .tables
+            .iter()
+            .position(|t| t.mode == mode && t.settings == settings)
+        {
+            Some(i) => &mut self.tables[i],
+            None => {
+                self.tables.push(Table {
+                    mode,
+                    settings,
+                    entries: Vec::new(),
+                });
+                self.tables.last_mut().unwrap()
+            }
+        };
+
+        let rank = table
+            .entries
+            .iter()
+            .position(|e| entry.score > e.score)
+            .unwrap_or(table.entries.len());
+        table.entries.insert(rank, entry);
+        table.entries.truncate(TABLE_LEN);
+    }
+}
+
+/// Where the high scores are kept, in the data directory of the user.
+pub fn path() -> PathBuf {
+    let data_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    data_dir
+        .unwrap_or_default()
+        .join("snakes_and_crabs")
+        .join("highscores.ron")
+}
+
+/// Today's date as `YYYY-MM-DD` in UTC.
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let days = (secs / 86_400) as i64;
+
+    // Civil date from the days since 1970-01-01, after Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+pub fn load_high_scores(mut commands: Commands) {
+    let high_scores = HighScores::load(&path()).unwrap_or_else(|err| {
+        warn!("Ignoring the high scores in {}: {err}", path().display());
+        HighScores::default()
+    });
+    commands.insert_resource(high_scores);
+}
+
+/// The human players of a finished game whose scores make it into the table,
+/// asked for their names one after the other.
+#[derive(Resource)]
+pub struct NameEntry {
+    pub players: Vec<usize>,
+    pub name: String,
+    /// Whether any player entered a name so far instead of skipping.
+    pub entered: bool,
+}
+
+#[derive(Component)]
+pub struct NameEntryText;
+
+/// Asks for names if a human player made it into the table. Replays do not count.
+pub fn start_name_entry(
+    mut commands: Commands,
+    state: Res<GameState>,
+    high_scores: Res<HighScores>,
+    replay: Res<ReplayMode>,
+) {
+    if replay.is_playing() {
+        return;
+    }
+    // The config of the game played, the menu may have changed the one in the app
+    let config = state.config();
+    let kinds = config.controller_kinds();
+    let mut players: Vec<_> = (0..state.snakes.len())
+        .filter(|i| kinds[*i] == ControllerKind::Keyboard)
+        .filter(|i| high_scores.qualifies(config, state.snakes[*i].score.score))
+        .collect();
+    // The best one first, it gets the place if only one is left
+    players.sort_by(|a, b| {
+        state.snakes[*b]
+            .score
+            .score
+            .total_cmp(&state.snakes[*a].score.score)
+    });
+    if !players.is_empty() {
+        commands.insert_resource(NameEntry {
+            players,
+            name: String::new(),
+            entered: false,
+        });
+    }
+}
+
+pub fn spawn_name_entry(mut commands: Commands, grid: Res<Grid>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: grid.lambda * 1.30,
+                color: Color::rgb(1.00, 0.34, 0.20),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(grid.lambda * 2.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_text_justify(JustifyText::Center),
+        NameEntryText,
+    ));
+}
+
+/// Typed characters go into the name, Enter adds it to the table and Escape
+/// skips the player, both move on to the next player. After the last one the
+/// high scores are saved and shown, unless all players skipped.
+#[allow(clippy::too_many_arguments)]
+pub fn enter_name(
+    mut commands: Commands,
+    state: Res<GameState>,
+    keycode: Res<ButtonInput<KeyCode>>,
+    mut ev_character: EventReader<ReceivedCharacter>,
+    mut entry: ResMut<NameEntry>,
+    mut high_scores: ResMut<HighScores>,
+    text: Query<Entity, With<NameEntryText>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // Keys still pressed from the game do not go into the name
+    if entry.is_added() {
+        ev_character.clear();
+        return;
+    }
+    for ev in ev_character.read() {
+        for c in ev.char.chars() {
+            if !c.is_control() && entry.name.chars().count() < MAX_NAME_LEN {
+                entry.name.push(c);
+            }
+        }
+    }
+    if keycode.just_pressed(KeyCode::Backspace) {
+        entry.name.pop();
+    }
+    let skipped = keycode.just_pressed(KeyCode::Escape);
+    let entered = keycode.just_pressed(KeyCode::Enter) && !entry.name.trim().is_empty();
+    if !skipped && !entered {
+        return;
+    }
+
+    let player = entry.players.remove(0);
+    if entered {
+        let score = &state.snakes[player].score;
+        high_scores.insert(
+            state.config(),
+            HighScore {
+                name: entry.name.trim().to_string(),
+                n_apples: score.n_apples,
+                score: score.score,
+                date: today(),
+            },
+        );
+        entry.entered = true;
+    }
+    entry.name.clear();
+
+    if entry.players.is_empty() {
+        commands.remove_resource::<NameEntry>();
+        if entry.entered {
+            if let Err(err) = high_scores.save(&path()) {
+                error!("Cannot save the high scores: {err}");
+            }
+            next_state.set(AppState::HighScores);
+        } else {
+            for entity in &text {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+pub fn render_name_entry(
+    state: Res<GameState>,
+    entry: Res<NameEntry>,
+    mut text: Query<&mut Text, With<NameEntryText>>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let who = match state.snakes.len() {
+        1 => "Your".to_string(),
+        _ => format!("Player {}, your", entry.players[0] + 1),
+    };
+    text.sections[0].value = format!(
+        "New high score! {who} name: {}_\nPress Enter when done or Escape to skip",
+        entry.name
+    );
+}
+
+pub fn despawn_name_entry(mut commands: Commands, text: Query<Entity, With<NameEntryText>>) {
+    for entity in &text {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<NameEntry>();
+}
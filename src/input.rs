@@ -0,0 +1,532 @@
+use std::{collections::HashMap, fmt, ops::Range, path::Path};
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, ConfigError},
+    game::Direction,
+};
+
+/// What a player wants, independent of the key or button that was pressed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Pause,
+    Restart,
+}
+
+impl Action {
+    pub const STEERING: [Action; 4] = [Action::Up, Action::Down, Action::Left, Action::Right];
+
+    pub fn direction(&self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            Action::Pause | Action::Restart => None,
+        }
+    }
+
+    fn from_direction(direction: Direction) -> Action {
+        match direction {
+            Direction::Up => Action::Up,
+            Direction::Down => Action::Down,
+            Direction::Left => Action::Left,
+            Direction::Right => Action::Right,
+        }
+    }
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ActionEvent {
+    pub action: Action,
+    /// The set of steering bindings it came from, `None` for pause and restart.
+    pub slot: Option<usize>,
+}
+
+/// A key or a gamepad button. In the settings file keys are written by their
+/// name like `KeyW` or `ArrowUp`, buttons like `Gamepad:DPadUp`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+}
+
+const BUTTON_PREFIX: &str = "Gamepad:";
+
+/// The keys that can be bound, looked up by name when reading the settings.
+const KEYS: [KeyCode; 64] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Escape,
+    KeyCode::Backspace,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Semicolon,
+    KeyCode::Slash,
+];
+
+const BUTTONS: [GamepadButtonType; 19] = [
+    GamepadButtonType::South,
+    GamepadButtonType::East,
+    GamepadButtonType::North,
+    GamepadButtonType::West,
+    GamepadButtonType::C,
+    GamepadButtonType::Z,
+    GamepadButtonType::LeftTrigger,
+    GamepadButtonType::LeftTrigger2,
+    GamepadButtonType::RightTrigger,
+    GamepadButtonType::RightTrigger2,
+    GamepadButtonType::Select,
+    GamepadButtonType::Start,
+    GamepadButtonType::Mode,
+    GamepadButtonType::LeftThumb,
+    GamepadButtonType::RightThumb,
+    GamepadButtonType::DPadUp,
+    GamepadButtonType::DPadDown,
+    GamepadButtonType::DPadLeft,
+    GamepadButtonType::DPadRight,
+];
+
+impl Binding {
+    /// Whether the binding is one of the keys or buttons that can be written
+    /// to the settings file.
+    pub fn is_bindable(&self) -> bool {
+        match self {
+            Binding::Key(key) => KEYS.contains(key),
+            Binding::Button(button) => BUTTONS.contains(button),
+        }
+    }
+
+    fn is_button(&self) -> bool {
+        matches!(self, Binding::Button(_))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{key:?}"),
+            Binding::Button(button) => write!(f, "{BUTTON_PREFIX}{button:?}"),
+        }
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let found = match name.strip_prefix(BUTTON_PREFIX) {
+            Some(button) => BUTTONS
+                .iter()
+                .find(|b| format!("{b:?}") == button)
+                .map(|b| Binding::Button(*b)),
+            None => KEYS
+                .iter()
+                .find(|k| format!("{k:?}") == name)
+                .map(|k| Binding::Key(*k)),
+        };
+        found.ok_or_else(|| format!("unknown key or button '{name}'"))
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        binding.to_string()
+    }
+}
+
+/// The bindings that steer one snake.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SteeringBindings {
+    pub up: Vec<Binding>,
+    pub down: Vec<Binding>,
+    pub left: Vec<Binding>,
+    pub right: Vec<Binding>,
+}
+
+impl SteeringBindings {
+    fn new(keys: [KeyCode; 4]) -> SteeringBindings {
+        let [up, down, left, right] = keys;
+        SteeringBindings {
+            up: vec![Binding::Key(up), Binding::Button(GamepadButtonType::DPadUp)],
+            down: vec![
+                Binding::Key(down),
+                Binding::Button(GamepadButtonType::DPadDown),
+            ],
+            left: vec![
+                Binding::Key(left),
+                Binding::Button(GamepadButtonType::DPadLeft),
+            ],
+            right: vec![
+                Binding::Key(right),
+                Binding::Button(GamepadButtonType::DPadRight),
+            ],
+        }
+    }
+}
+
+/// Keys and gamepad buttons of all actions, read from the settings file.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    /// Steering of the keyboard players in order, the n-th gamepad uses the
+    /// buttons of the n-th set.
+    pub players: Vec<SteeringBindings>,
+    pub pause: Vec<Binding>,
+    pub restart: Vec<Binding>,
+    /// How far a stick has to be pushed to steer, between 0 and 1.
+    pub stick_threshold: f32,
+    /// How far in pixels a mouse drag or a touch swipe has to go to steer.
+    pub swipe_distance: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            players: vec![
+                SteeringBindings::new([KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyA, KeyCode::KeyD]),
+                SteeringBindings::new([
+                    KeyCode::ArrowUp,
+                    KeyCode::ArrowDown,
+                    KeyCode::ArrowLeft,
+                    KeyCode::ArrowRight,
+                ]),
+            ],
+            pause: vec![
+                Binding::Key(KeyCode::KeyP),
+                Binding::Key(KeyCode::Escape),
+                Binding::Button(GamepadButtonType::Start),
+            ],
+            restart: vec![
+                Binding::Key(KeyCode::KeyR),
+                Binding::Button(GamepadButtonType::South),
+            ],
+            stick_threshold: 0.5,
+            swipe_distance: 30.,
+        }
+    }
+}
+
+impl Bindings {
+    /// Reads the bindings from a `.ron` file, the defaults are used if there is none.
+    pub fn load(path: &Path) -> Result<Bindings, ConfigError> {
+        if !path.exists() {
+            return Ok(Bindings::default());
+        }
+
+        let content = config::read_file(path)?;
+        ron::from_str(&content).map_err(|err| ConfigError::InvalidBindings(err.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        config::write_ron(path, self, true)
+    }
+
+    /// Fails if some of the `n_keyboards` players could not steer.
+    pub fn check(&self, n_keyboards: usize) -> Result<(), ConfigError> {
+        if self.players.len() < n_keyboards {
+            return Err(ConfigError::InvalidBindings(format!(
+                "{n_keyboards} players steer by keyboard or gamepad, but there are only {} sets of steering bindings",
+                self.players.len()
+            )));
+        }
+        if !(self.stick_threshold > 0. && self.stick_threshold < 1.) {
+            return Err(ConfigError::InvalidBindings(format!(
+                "the stick threshold must be between 0 and 1, got {}",
+                self.stick_threshold
+            )));
+        }
+        if !(self.swipe_distance > 0. && self.swipe_distance.is_finite()) {
+            return Err(ConfigError::InvalidBindings(format!(
+                "the swipe distance must be positive, got {}",
+                self.swipe_distance
+            )));
+        }
+        Ok(())
+    }
+
+    /// The bindings of the action, `slot` selects the set of steering bindings.
+    pub fn get(&self, action: Action, slot: usize) -> &[Binding] {
+        let steering = self.players.get(slot);
+        match action {
+            Action::Pause => &self.pause,
+            Action::Restart => &self.restart,
+            Action::Up => steering.map_or(&[], |s| &s.up),
+            Action::Down => steering.map_or(&[], |s| &s.down),
+            Action::Left => steering.map_or(&[], |s| &s.left),
+            Action::Right => steering.map_or(&[], |s| &s.right),
+        }
+    }
+
+    fn get_mut(&mut self, action: Action, slot: usize) -> &mut Vec<Binding> {
+        match action {
+            Action::Pause => &mut self.pause,
+            Action::Restart => &mut self.restart,
+            Action::Up => &mut self.players[slot].up,
+            Action::Down => &mut self.players[slot].down,
+            Action::Left => &mut self.players[slot].left,
+            Action::Right => &mut self.players[slot].right,
+        }
+    }
+
+    /// Binds the key or button to the action instead of the previous key or
+    /// button. It is taken away from any other action that used it.
+    pub fn rebind(&mut self, action: Action, slot: usize, binding: Binding) {
+        for steering in &mut self.players {
+            for bindings in [
+                &mut steering.up,
+                &mut steering.down,
+                &mut steering.left,
+                &mut steering.right,
+            ] {
+                bindings.retain(|b| *b != binding);
+            }
+        }
+        self.pause.retain(|b| *b != binding);
+        self.restart.retain(|b| *b != binding);
+
+        let bindings = self.get_mut(action, slot);
+        bindings.retain(|b| b.is_button() != binding.is_button());
+        bindings.push(binding);
+    }
+
+    /// The names of the bindings for the screens, like "P or Escape".
+    pub fn describe(&self, action: Action, slot: usize) -> String {
+        let names: Vec<_> = self
+            .get(action, slot)
+            .iter()
+            .map(|b| b.to_string())
+            .collect();
+        match names.as_slice() {
+            [] => "nothing".to_string(),
+            names => names.join(" or "),
+        }
+    }
+
+    /// The actions bound to the key or button. Steering is only looked up in
+    /// the sets of `slots`.
+    fn actions(&self, binding: Binding, slots: Range<usize>) -> Vec<ActionEvent> {
+        let mut events = Vec::new();
+        for slot in slots {
+            for action in Action::STEERING {
+                if self.get(action, slot).contains(&binding) {
+                    events.push(ActionEvent {
+                        action,
+                        slot: Some(slot),
+                    });
+                }
+            }
+        }
+        for action in [Action::Pause, Action::Restart] {
+            if self.get(action, 0).contains(&binding) {
+                events.push(ActionEvent { action, slot: None });
+            }
+        }
+        events
+    }
+}
+
+/// The direction a stick is pushed or a swipe goes to, if it is long enough.
+/// The y axis points up.
+fn stick_direction(stick: Vec2, threshold: f32) -> Option<Direction> {
+    if stick.length() < threshold {
+        None
+    } else if stick.x.abs() > stick.y.abs() {
+        Some(if stick.x > 0. {
+            Direction::Right
+        } else {
+            Direction::Left
+        })
+    } else {
+        Some(if stick.y > 0. {
+            Direction::Up
+        } else {
+            Direction::Down
+        })
+    }
+}
+
+/// Turns the keys and gamepad buttons pressed since the last frame into
+/// actions. Keys may belong to any set of steering bindings, the n-th gamepad
+/// steers with the n-th set, with its buttons as well as its left stick.
+pub fn read_actions(
+    bindings: Res<Bindings>,
+    keycode: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut sticks: Local<HashMap<Gamepad, Direction>>,
+    mut ev_action: EventWriter<ActionEvent>,
+) {
+    for key in keycode.get_just_pressed() {
+        ev_action.send_batch(bindings.actions(Binding::Key(*key), 0..bindings.players.len()));
+    }
+
+    for (slot, gamepad) in gamepads.iter().enumerate() {
+        for button in buttons.get_just_pressed() {
+            if button.gamepad == gamepad {
+                ev_action.send_batch(
+                    bindings.actions(Binding::Button(button.button_type), slot..slot + 1),
+                );
+            }
+        }
+
+        // A stick only steers once when it is pushed to another direction
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        let stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        match stick_direction(stick, bindings.stick_threshold) {
+            Some(direction) if sticks.get(&gamepad) != Some(&direction) => {
+                sticks.insert(gamepad, direction);
+                ev_action.send(ActionEvent {
+                    action: Action::from_direction(direction),
+                    slot: Some(slot),
+                });
+            }
+            Some(_) => {}
+            None => {
+                sticks.remove(&gamepad);
+            }
+        }
+    }
+}
+
+/// A mouse button held down or a finger on the screen.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Pointer {
+    Mouse,
+    Touch(u64),
+}
+
+/// Where a pointer went down or last steered, and whether it steered since
+/// it went down.
+#[derive(Clone, Copy, Debug)]
+pub struct Swipe {
+    anchor: Vec2,
+    steered: bool,
+}
+
+/// Turns mouse drags and touch swipes into steering of the first set of
+/// bindings. Every `swipe_distance` pixels in a new direction steer again, so
+/// one drag can turn several times. A tap without swiping restarts.
+pub fn read_swipes(
+    bindings: Res<Bindings>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut swipes: Local<HashMap<Pointer, Swipe>>,
+    mut ev_action: EventWriter<ActionEvent>,
+) {
+    let cursor = window.get_single().ok().and_then(|w| w.cursor_position());
+    let mut pointers: Vec<_> = touches
+        .iter()
+        .map(|touch| (Pointer::Touch(touch.id()), touch.position()))
+        .collect();
+    if let Some(cursor) = cursor.filter(|_| mouse.pressed(MouseButton::Left)) {
+        pointers.push((Pointer::Mouse, cursor));
+    }
+
+    for (pointer, position) in pointers {
+        let swipe = swipes.entry(pointer).or_insert(Swipe {
+            anchor: position,
+            steered: false,
+        });
+        // Window coordinates grow downwards
+        let delta = (position - swipe.anchor) * Vec2::new(1., -1.);
+        if delta.length() < bindings.swipe_distance {
+            continue;
+        }
+        swipe.anchor = position;
+        swipe.steered = true;
+        if let Some(direction) = stick_direction(delta, 0.) {
+            ev_action.send(ActionEvent {
+                action: Action::from_direction(direction),
+                slot: Some(0),
+            });
+        }
+    }
+
+    let released = touches
+        .iter_just_released()
+        .chain(touches.iter_just_canceled())
+        .map(|touch| Pointer::Touch(touch.id()))
+        .chain(
+            mouse
+                .just_released(MouseButton::Left)
+                .then_some(Pointer::Mouse),
+        );
+    for pointer in released {
+        if swipes.remove(&pointer).is_some_and(|swipe| !swipe.steered) {
+            ev_action.send(ActionEvent {
+                action: Action::Restart,
+                slot: None,
+            });
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{collections::HashSet, path::Path};
+
+use bevy::{ecs::system::Resource, math::IVec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, ConfigError},
+    game::{Board, Direction},
+    BoundaryMode, Config,
+};
+
+/// A level as it is stored on disk, usually in a `.ron` file:
+///
+/// ```ron
+/// (
+///     name: "Pillars",
+///     direction: Right,
+///     map: [
+///         "#########",
+///         "#.......#",
+///         "#.#.S.#.#",
+///         "#..AAA..#",
+///         "#########",
+///     ],
+/// )
+/// ```
+///
+/// In the map `#` is a wall, `.` a free cell, `S` the head of the snake and `A`
+/// a cell apples may spawn on. Without any `A` apples spawn on every free cell.
+/// The number of rows and columns must be uneven. In a two-player game the
+/// second snake starts at the point reflection of `S` through the center,
+/// heading the opposite way.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub name: String,
+    pub direction: Direction,
+    pub map: Vec<String>,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct Level {
+    pub name: String,
+    pub board: Board,
+    pub walls: HashSet<IVec2>,
+    /// Cell of the head of the first snake, the body trails behind it opposite
+    /// to `direction`. See [`Level::spawn_of`] for the other snakes.
+    pub spawn: IVec2,
+    pub direction: Direction,
+    /// Cells apples may spawn on, empty if apples may spawn on any free cell.
+    pub apple_zone: HashSet<IVec2>,
+}
+
+impl Level {
+    /// The classic level: an empty rectangle of the configured size, a single
+    /// snake starts in the center, two snakes halfway to the top and bottom.
+    /// It is surrounded by walls unless the snakes wrap around.
+    pub fn open(config: &Config) -> Level {
+        let board = Board::new(config.n_horizontal_cells, config.n_vertical_cells);
+        let walls = match config.boundary {
+            BoundaryMode::Solid => board.get_wall().into_iter().collect(),
+            BoundaryMode::Wrap => HashSet::new(),
+        };
+        let spawn = match config.n_players {
+            1 => IVec2::ZERO,
+            _ => IVec2::new(0, board.max_idx_y / 2),
+        };
+
+        Level {
+            name: "Open".to_string(),
+            board,
+            walls,
+            spawn,
+            direction: Direction::Right,
+            apple_zone: HashSet::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Level, ConfigError> {
+        let content = config::read_file(path)?;
+        let file: LevelFile =
+            ron::from_str(&content).map_err(|err| ConfigError::InvalidLevel(err.to_string()))?;
+        Level::parse(&file)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        config::write_ron(path, &self.to_file(), true)
+    }
+
+    pub fn to_file(&self) -> LevelFile {
+        let map = (-self.board.max_idx_y..=self.board.max_idx_y)
+            .rev()
+            .map(|idx_y| {
+                (-self.board.max_idx_x..=self.board.max_idx_x)
+                    .map(|idx_x| {
+                        let cell = IVec2::new(idx_x, idx_y);
+                        if self.is_wall(cell) {
+                            '#'
+                        } else if cell == self.spawn {
+                            'S'
+                        } else if self.apple_zone.contains(&cell) {
+                            'A'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        LevelFile {
+            name: self.name.clone(),
+            direction: self.direction,
+            map,
+        }
+    }
+
+    pub fn parse(file: &LevelFile) -> Result<Level, ConfigError> {
+        let n_rows = file.map.len();
+        let n_cols = file.map.first().map_or(0, |row| row.chars().count());
+        if n_rows.is_multiple_of(2) || n_cols.is_multiple_of(2) {
+            return Err(ConfigError::InvalidLevel(format!(
+                "the map must have an uneven number of rows and columns, got {n_cols}x{n_rows}"
+            )));
+        }
+
+        let board = Board::new(n_cols as u32, n_rows as u32);
+        let mut walls = HashSet::new();
+        let mut apple_zone = HashSet::new();
+        let mut spawn = None;
+
+        for (row, line) in file.map.iter().enumerate() {
+            if line.chars().count() != n_cols {
+                return Err(ConfigError::InvalidLevel(format!(
+                    "row {row} of the map has a different length than the first row"
+                )));
+            }
+
+            for (col, c) in line.chars().enumerate() {
+                let cell = IVec2::new(col as i32 - board.max_idx_x, board.max_idx_y - row as i32);
+                match c {
+                    '#' => {
+                        walls.insert(cell);
+                    }
+                    'A' => {
+                        apple_zone.insert(cell);
+                    }
+                    'S' if spawn.is_none() => spawn = Some(cell),
+                    'S' => {
+                        return Err(ConfigError::InvalidLevel(
+                            "the map has more than one spawn point".to_string(),
+                        ))
+                    }
+                    '.' | ' ' => {}
+                    _ => {
+                        return Err(ConfigError::InvalidLevel(format!(
+                            "unknown character '{c}' in row {row} of the map"
+                        )))
+                    }
+                }
+            }
+        }
+
+        let Some(spawn) = spawn else {
+            return Err(ConfigError::InvalidLevel(
+                "the map has no spawn point 'S'".to_string(),
+            ));
+        };
+
+        Ok(Level {
+            name: file.name.clone(),
+            board,
+            walls,
+            spawn,
+            direction: file.direction,
+            apple_zone,
+        })
+    }
+
+    pub fn is_wall(&self, cell: IVec2) -> bool {
+        self.walls.contains(&cell)
+    }
+
+    /// Whether the snake may move onto the cell.
+    pub fn is_free(&self, cell: IVec2) -> bool {
+        self.board.contains(cell) && !self.is_wall(cell)
+    }
+
+    /// Whether an apple may spawn on the cell if no snake is on it.
+    pub fn is_apple_cell(&self, cell: IVec2) -> bool {
+        self.is_free(cell) && (self.apple_zone.is_empty() || self.apple_zone.contains(&cell))
+    }
+
+    /// Head cell and direction of the snake of the player. The second snake
+    /// starts at the point reflection of the first one, heading the other way.
+    pub fn spawn_of(&self, player: usize) -> (IVec2, Direction) {
+        match player {
+            0 => (self.spawn, self.direction),
+            _ => (-self.spawn, self.direction.opposite()),
+        }
+    }
+
+    /// The cells of the snake of the player at the start, head first.
+    pub fn initial_snake(&self, player: usize, length: u32) -> Vec<IVec2> {
+        let (spawn, direction) = self.spawn_of(player);
+        let delta = direction.opposite().delta();
+        (0..length.max(1) as i32)
+            .map(|i| spawn + delta * i)
+            .collect()
+    }
+
+    /// Checks that the snakes, the apples and the crabs of the config fit into the level.
+    pub fn check(&self, config: &Config) -> Result<(), ConfigError> {
+        let players = 0..config.n_players as usize;
+        let max = players
+            .clone()
+            .map(|player| {
+                let (spawn, direction) = self.spawn_of(player);
+                let delta = direction.opposite().delta();
+                (0..)
+                    .take_while(|i| self.is_free(spawn + delta * *i))
+                    .count() as u32
+            })
+            .min()
+            .unwrap_or_default();
+        if config.initial_bodylength == 0 || config.initial_bodylength > max {
+            return Err(ConfigError::InvalidBodyLength {
+                length: config.initial_bodylength,
+                max,
+            });
+        }
+
+        let mut snakes = HashSet::new();
+        for player in players {
+            for cell in self.initial_snake(player, config.initial_bodylength) {
+                if !snakes.insert(cell) {
+                    return Err(ConfigError::InvalidLevel(format!(
+                        "the snakes of the players overlap at {cell}"
+                    )));
+                }
+            }
+        }
+
+        let max = self
+            .board
+            .cells()
+            .filter(|cell| self.is_apple_cell(*cell) && !snakes.contains(cell))
+            .count() as u32;
+        if config.n_apples == 0 || config.n_apples > max {
+            return Err(ConfigError::InvalidAppleCount {
+                n_apples: config.n_apples,
+                max,
+            });
+        }
+
+        // Crabs may also spawn outside the apple zone
+        let max = self
+            .board
+            .cells()
+            .filter(|cell| self.is_free(*cell) && !snakes.contains(cell))
+            .count() as u32
+            - config.n_apples;
+        if config.crabs.n_crabs > max {
+            return Err(ConfigError::InvalidCrabCount {
+                n_crabs: config.crabs.n_crabs,
+                max,
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -1,82 +1,271 @@
 mod apples;
 mod bloom_example;
+mod config;
+pub mod controller;
+pub mod crab;
+mod editor;
+pub mod game;
 mod geometry;
 mod grid;
+mod highscores;
+pub mod input;
+pub mod level;
+pub mod occupancy;
 mod playground;
+mod rebinding;
+pub mod replay;
 mod score;
+mod screens;
 pub mod snake;
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use bevy::{
     app::PluginGroupBuilder,
     // core_pipeline::bloom::BloomSettings,
     core_pipeline::tonemapping::Tonemapping,
+    ecs::schedule::SystemConfigs,
+    input::InputSystem,
     prelude::*,
     window::WindowMode,
 };
+use game::GameState;
 use grid::Grid;
-use snake::{Position, Velocity};
+use highscores::NameEntry;
+use input::{ActionEvent, Bindings};
+use level::Level;
+use replay::ReplayMode;
+
+pub use config::{
+    AppleKindConfig, BoundaryMode, Config, ConfigError, ControllerKind, CrabBehavior, CrabConfig,
+    Difficulty, ScoringRules, SpeedCurve, TimeBonus, WinCondition,
+};
 
 use crate::{
-    apples::{AppleEatenEvent, RelocateAppleEvent},
+    apples::{AppleEatenEvent, AppleExpiredEvent, RelocateAppleEvent},
     score::ScoreIncreasedEvent,
     snake::MoveEvent,
 };
 
-#[derive(Resource)]
-pub struct Config {
-    pub n_vertical_cells: u32,   // Should be uneven
-    pub n_horizontal_cells: u32, // Should be uneven
-    pub initial_bodylength: u32,
-    pub initial_speed: f32,
-    pub n_elements_per_apple: u32,
-    pub score_increment: u32,
+#[derive(Event)]
+pub struct GameOverEvent();
+
+#[derive(Event)]
+pub struct LevelCompleteEvent;
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+    LevelComplete,
+    Editor,
+    Rebinding,
+    HighScores,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            n_vertical_cells: 25,
-            n_horizontal_cells: 45,
-            initial_bodylength: 3,
-            initial_speed: 10.,
-            n_elements_per_apple: 1,
-            score_increment: 1,
-        }
-    }
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
 }
 
-fn set_config(mut commands: Commands) {
-    commands.insert_resource(Config {
-        // n_vertical_cells: 95,
-        initial_bodylength: 10,
-        initial_speed: 10.,
-        ..default()
-    });
+/// Starts a new simulation, all its random decisions follow from the seed.
+/// A replay is played on its own level.
+fn reset_game_state(
+    mut commands: Commands,
+    config: Res<Config>,
+    level: Res<Level>,
+    replay: Res<ReplayMode>,
+) {
+    let (seed, level) = match replay.as_ref() {
+        ReplayMode::Play(replay) => (
+            replay.seed,
+            replay
+                .level()
+                .expect("the level of the replay was checked when it was loaded"),
+        ),
+        _ => (config.seed.unwrap_or_else(time_seed), level.clone()),
+    };
+    commands.insert_resource(GameState::new(&config, &level, seed));
 }
 
-#[derive(Event)]
-pub struct GameOverEvent();
+/// Replaces the current game by a fresh one: the snakes, the apples and the
+/// crabs are despawned, `GameState` (speed, score, apples) is reset and everything respawned.
+fn new_game() -> SystemConfigs {
+    (
+        snake::despawn_snake,
+        apples::despawn_apples,
+        crab::despawn_crabs,
+        reset_game_state,
+        snake::set_tick_rate,
+        snake::spawn_snake,
+        apples::spawn_apples,
+        crab::spawn_crabs,
+    )
+        .chain()
+}
 
-pub fn run() {
+pub fn run(config: Config, level: Level, bindings: Bindings, replay: ReplayMode) {
     println!("Welcome to Snakes and Crabs.");
     App::new()
-        .add_plugins(get_full_screen_default_plugins())
+        .add_plugins(get_default_plugins(&config))
+        .insert_resource(config)
+        .insert_resource(level)
+        .insert_resource(bindings)
+        .insert_resource(replay)
+        .add_event::<ActionEvent>()
         .add_event::<MoveEvent>()
         .add_event::<GameOverEvent>()
         .add_event::<AppleEatenEvent>()
+        .add_event::<AppleExpiredEvent>()
         .add_event::<RelocateAppleEvent>()
+        .add_event::<LevelCompleteEvent>()
         .add_event::<ScoreIncreasedEvent>()
+        .init_state::<AppState>()
         .add_systems(
             Startup,
             (
-                set_config,
+                reset_game_state,
+                highscores::load_high_scores,
                 playground::spawn_playing_ground,
+                (setup_camera, score::spawn_score),
+            )
+                .chain(),
+        )
+        .add_systems(
+            PreUpdate,
+            (
+                input::read_actions,
+                // The editor takes the mouse for itself
+                input::read_swipes.run_if(not(in_state(AppState::Editor))),
+            )
+                .after(InputSystem)
+                // Keys are taken as they are while rebinding or typing a name
+                .run_if(not(in_state(AppState::Rebinding)))
+                .run_if(not(resource_exists::<NameEntry>)),
+        )
+        .add_systems(
+            OnTransition {
+                from: AppState::Menu,
+                to: AppState::Playing,
+            },
+            new_game(),
+        )
+        .add_systems(
+            OnTransition {
+                from: AppState::GameOver,
+                to: AppState::Playing,
+            },
+            new_game(),
+        )
+        .add_systems(
+            OnTransition {
+                from: AppState::LevelComplete,
+                to: AppState::Playing,
+            },
+            new_game(),
+        )
+        .add_systems(
+            OnTransition {
+                from: AppState::HighScores,
+                to: AppState::Playing,
+            },
+            new_game(),
+        )
+        .add_systems(
+            FixedUpdate,
+            (snake::move_snake, snake::set_tick_rate)
+                .chain()
+                .run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (
+                snake::steer_snake.run_if(in_state(AppState::Playing)),
                 (
-                    setup_camera,
-                    snake::spawn_snake,
-                    apples::spawn_apple,
-                    score::spawn_score,
+                    snake::move_body,
+                    snake::snake_grows,
+                    snake::snake_shrinks,
+                    crab::move_crabs,
                 ),
+                apples::relocate_apple,
+                apples::hide_waiting_apples,
+                apples::apple_countdown,
+                score::render_score,
+                screens::game_over.run_if(in_state(AppState::Playing)),
+            )
+                .chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                playground::resize_grid,
+                (playground::relayout_cells, score::relayout_score)
+                    .run_if(resource_changed::<Grid>),
+            )
+                .chain(),
+        )
+        .add_systems(Update, screens::start_game.run_if(in_state(AppState::Menu)))
+        .add_systems(
+            Update,
+            screens::restart_or_leave
+                .run_if(
+                    in_state(AppState::GameOver)
+                        .or_else(in_state(AppState::LevelComplete))
+                        .or_else(in_state(AppState::HighScores)),
+                )
+                // Escape skips the name entry instead, also in the frame it ends
+                .run_if(not(resource_exists::<NameEntry>))
+                .before(highscores::enter_name),
+        )
+        .add_systems(
+            Update,
+            (highscores::enter_name, highscores::render_name_entry)
+                .chain()
+                .distributive_run_if(resource_exists::<NameEntry>),
+        )
+        .add_systems(OnEnter(AppState::Menu), screens::spawn_menu)
+        .add_systems(
+            Update,
+            (
+                screens::despawn_screen::<screens::MenuScreen>,
+                screens::spawn_menu,
+            )
+                .chain()
+                .run_if(in_state(AppState::Menu).and_then(resource_changed::<Config>)),
+        )
+        .add_systems(
+            OnExit(AppState::Menu),
+            screens::despawn_screen::<screens::MenuScreen>,
+        )
+        .add_systems(
+            Update,
+            screens::toggle_pause
+                .run_if(in_state(AppState::Playing).or_else(in_state(AppState::Paused)))
+                // A game that ends in the same frame goes to its end screen, not the pause
+                .before(screens::game_over),
+        )
+        .add_systems(
+            OnEnter(AppState::Paused),
+            (screens::freeze_time, screens::spawn_pause),
+        )
+        .add_systems(
+            OnExit(AppState::Paused),
+            (
+                screens::unfreeze_time,
+                screens::despawn_screen::<screens::PauseScreen>,
+            ),
+        )
+        .add_systems(
+            OnEnter(AppState::GameOver),
+            (
+                replay::save_replay,
+                highscores::start_name_entry,
+                screens::spawn_game_over,
+                highscores::spawn_name_entry.run_if(resource_exists::<NameEntry>),
             )
                 .chain(),
         )
@@ -84,28 +273,81 @@ pub fn run() {
             Update,
             (
                 (
-                    snake::speed_up,
-                    snake::steer_snake,
-                    snake::move_snake,
-                    playground::snake_hits_wall,
-                    snake::snake_hits_itself,
-                    snake::move_body,
+                    screens::despawn_screen::<screens::GameOverScreen>,
+                    screens::spawn_game_over,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::GameOver)),
+                (
+                    screens::despawn_screen::<screens::ResultsScreen>,
+                    screens::spawn_results,
                 )
-                    .chain(),
-                snake::snake_grows,
+                    .chain()
+                    .run_if(in_state(AppState::LevelComplete)),
+            )
+                // Shows the restart hint once all players skipped the name entry
+                .run_if(resource_removed::<NameEntry>())
+                .after(highscores::enter_name),
+        )
+        .add_systems(
+            OnExit(AppState::GameOver),
+            (
+                screens::despawn_screen::<screens::GameOverScreen>,
+                highscores::despawn_name_entry,
             ),
         )
+        .add_systems(
+            OnEnter(AppState::Editor),
+            (
+                snake::despawn_snake,
+                apples::despawn_apples,
+                crab::despawn_crabs,
+                editor::enter_editor,
+                editor::respawn_markers,
+            ),
+        )
+        .add_systems(OnExit(AppState::Editor), editor::exit_editor)
         .add_systems(
             Update,
             (
-                apples::apple_eaten,
-                apples::find_free_cells,
-                apples::relocate_apple,
+                (editor::editor_keys, editor::edit_cells),
+                (playground::respawn_walls, editor::respawn_markers)
+                    .run_if(resource_changed::<Level>),
+                editor::render_hud,
+            )
+                .chain()
+                .run_if(in_state(AppState::Editor)),
+        )
+        .add_systems(OnEnter(AppState::Rebinding), rebinding::enter_rebinding)
+        .add_systems(OnExit(AppState::Rebinding), rebinding::exit_rebinding)
+        .add_systems(
+            Update,
+            (rebinding::rebind, rebinding::render_hud)
+                .chain()
+                .run_if(in_state(AppState::Rebinding)),
+        )
+        .add_systems(
+            OnEnter(AppState::LevelComplete),
+            (
+                replay::save_replay,
+                highscores::start_name_entry,
+                screens::spawn_results,
+                highscores::spawn_name_entry.run_if(resource_exists::<NameEntry>),
             )
                 .chain(),
         )
-        .add_systems(Update, (score::increment_score, score::render_score))
-        .add_systems(Update, game_over_teleport_to_center)
+        .add_systems(
+            OnExit(AppState::LevelComplete),
+            (
+                screens::despawn_screen::<screens::ResultsScreen>,
+                highscores::despawn_name_entry,
+            ),
+        )
+        .add_systems(OnEnter(AppState::HighScores), screens::spawn_high_scores)
+        .add_systems(
+            OnExit(AppState::HighScores),
+            screens::despawn_screen::<screens::HighScoreScreen>,
+        )
         .run();
 }
 
@@ -123,29 +365,19 @@ fn setup_camera(mut commands: Commands) {
     ));
 }
 
-fn get_full_screen_default_plugins() -> PluginGroupBuilder {
+fn get_default_plugins(config: &Config) -> PluginGroupBuilder {
+    let mode = if config.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+
     DefaultPlugins.set(WindowPlugin {
         primary_window: Some(Window {
-            resizable: false,
-            mode: WindowMode::BorderlessFullscreen,
+            resizable: !config.fullscreen,
+            mode,
             ..default()
         }),
         ..default()
     })
 }
-
-fn game_over_teleport_to_center(
-    grid: Res<Grid>,
-    mut snake: Query<(&mut Position, &Velocity)>,
-    mut ev_game_over: EventReader<GameOverEvent>,
-) {
-    for _ in ev_game_over.read() {
-        let snake = snake.get_single_mut().unwrap();
-
-        let mut pos = snake.0;
-
-        let cell = grid.get_cell_from_position(0., 0.);
-        pos.x = cell.pos_x;
-        pos.y = cell.pos_y;
-    }
-}
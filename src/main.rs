@@ -1,5 +1,252 @@
-use snakes_and_crabs::run;
+use std::{path::PathBuf, process::ExitCode};
 
-fn main() {
-    run();
+use clap::Parser;
+use snakes_and_crabs::{
+    input::Bindings,
+    level::Level,
+    replay::{Replay, ReplayMode},
+    run, BoundaryMode, Config, ConfigError, ControllerKind, CrabBehavior, Difficulty, WinCondition,
+};
+
+/// Snakes and Crabs. Settings are read from the optional config file
+/// and then overridden by the flags given on the command line.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Config file in RON (.ron) or TOML (.toml) format
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Level file in RON format, replaces the open board of --width x --height
+    #[arg(short, long)]
+    level: Option<PathBuf>,
+
+    /// Settings file of the keys and gamepad buttons in RON format
+    #[arg(short, long)]
+    bindings: Option<PathBuf>,
+
+    /// Seed of the random apples and crabs, as shown after a game, to play the same board again
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Number of players on the same keyboard, by default the first steers with WASD, the second with the arrows
+    #[arg(short, long)]
+    players: Option<u32>,
+
+    /// Add a computer player: greedy, shortest-path or hamiltonian
+    #[arg(long, value_parser = ["greedy", "shortest-path", "hamiltonian"])]
+    cpu: Option<String>,
+
+    /// Number of horizontal cells, walls included (uneven)
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Number of vertical cells, walls included (uneven)
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Initial length of the snake
+    #[arg(long)]
+    initial_length: Option<u32>,
+
+    /// Preset of speed and growth: easy, normal, hard or insane, the flags below override it
+    #[arg(short, long, value_parser = ["easy", "normal", "hard", "insane"])]
+    difficulty: Option<String>,
+
+    /// Initial speed in cells per second
+    #[arg(long)]
+    initial_speed: Option<f32>,
+
+    /// Maximum speed in cells per second
+    #[arg(long)]
+    max_speed: Option<f32>,
+
+    /// Number of segments the snake grows per apple
+    #[arg(long)]
+    elements_per_apple: Option<u32>,
+
+    /// Number of apples on the board at the same time
+    #[arg(long)]
+    apples: Option<u32>,
+
+    /// Number of ticks after which an uneaten apple moves elsewhere
+    #[arg(long)]
+    apple_lifetime: Option<u32>,
+
+    /// Number of apples counted per eaten apple
+    #[arg(long)]
+    score_increment: Option<u32>,
+
+    /// Number of crabs on the board
+    #[arg(long)]
+    crabs: Option<u32>,
+
+    /// Let the crabs chase the snakes instead of the apples
+    #[arg(long)]
+    chasing_crabs: bool,
+
+    /// Complete the level after eating this many apples
+    #[arg(long, conflicts_with = "win_score")]
+    win_apples: Option<u32>,
+
+    /// Complete the level when reaching this score within the time limit
+    #[arg(long, requires = "time_limit")]
+    win_score: Option<f32>,
+
+    /// Time limit in seconds to reach the score given by --win-score
+    #[arg(long, requires = "win_score")]
+    time_limit: Option<f32>,
+
+    /// Let the snake leave the board on one side and re-enter on the other
+    #[arg(long)]
+    wrap: bool,
+
+    /// Write every finished game to this replay file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Play the game of a replay file, only the window mode is taken from the other settings
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Run in a window instead of fullscreen
+    #[arg(long, conflicts_with = "fullscreen")]
+    windowed: bool,
+
+    /// Run fullscreen
+    #[arg(long)]
+    fullscreen: bool,
+}
+
+impl Cli {
+    fn into_config(self) -> Result<(Config, Level, Bindings, ReplayMode), ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(level) = self.level {
+            config.level = Some(level);
+        }
+        if let Some(bindings) = self.bindings {
+            config.bindings = bindings;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+        }
+        if let Some(players) = self.players {
+            config.n_players = players;
+        }
+        if let Some(cpu) = &self.cpu {
+            let kind = match cpu.as_str() {
+                "greedy" => ControllerKind::Greedy,
+                "shortest-path" => ControllerKind::ShortestPath,
+                _ => ControllerKind::Hamiltonian,
+            };
+            config
+                .controllers
+                .resize(config.n_players as usize, ControllerKind::Keyboard);
+            config.controllers.push(kind);
+            config.n_players += 1;
+        }
+        if let Some(width) = self.width {
+            config.n_horizontal_cells = width;
+        }
+        if let Some(height) = self.height {
+            config.n_vertical_cells = height;
+        }
+        if let Some(initial_length) = self.initial_length {
+            config.initial_bodylength = initial_length;
+        }
+        if let Some(difficulty) = &self.difficulty {
+            let difficulty = match difficulty.as_str() {
+                "easy" => Difficulty::Easy,
+                "normal" => Difficulty::Normal,
+                "hard" => Difficulty::Hard,
+                _ => Difficulty::Insane,
+            };
+            difficulty.apply(&mut config);
+        }
+        if let Some(initial_speed) = self.initial_speed {
+            config.initial_speed = initial_speed;
+            // Only a maximum speed given with it can conflict, not the one of a preset
+            config.max_speed = config.max_speed.filter(|max| *max >= initial_speed);
+        }
+        if let Some(max_speed) = self.max_speed {
+            config.max_speed = Some(max_speed);
+        }
+        if let Some(elements_per_apple) = self.elements_per_apple {
+            config.n_elements_per_apple = elements_per_apple;
+        }
+        if let Some(apples) = self.apples {
+            config.n_apples = apples;
+        }
+        if let Some(apple_lifetime) = self.apple_lifetime {
+            config.apple_lifetime = Some(apple_lifetime);
+        }
+        if let Some(score_increment) = self.score_increment {
+            config.score_increment = score_increment;
+        }
+        if let Some(n_crabs) = self.crabs {
+            config.crabs.n_crabs = n_crabs;
+        }
+        if self.chasing_crabs {
+            config.crabs.behavior = CrabBehavior::Chase;
+        }
+        if let Some(n_apples) = self.win_apples {
+            config.win_condition = WinCondition::Apples(n_apples);
+        }
+        if let (Some(target), Some(time_limit)) = (self.win_score, self.time_limit) {
+            config.win_condition = WinCondition::Score { target, time_limit };
+        }
+        if self.wrap {
+            config.boundary = BoundaryMode::Wrap;
+        }
+        if self.windowed {
+            config.fullscreen = false;
+        }
+        if self.fullscreen {
+            config.fullscreen = true;
+        }
+
+        let replay = match (self.record, self.replay) {
+            (Some(path), _) => ReplayMode::Record(path),
+            (None, Some(path)) => ReplayMode::Play(Box::new(Replay::load(&path)?)),
+            (None, None) => ReplayMode::Off,
+        };
+        if let ReplayMode::Play(replay) = &replay {
+            config = Config {
+                fullscreen: config.fullscreen,
+                bindings: config.bindings,
+                ..replay.config.clone()
+            };
+        }
+
+        config.validate()?;
+        let level = match &replay {
+            ReplayMode::Play(replay) => replay.level()?,
+            _ => config.load_level()?,
+        };
+        let bindings = Bindings::load(&config.bindings)?;
+        let n_keyboards = config
+            .controller_kinds()
+            .iter()
+            .filter(|kind| **kind == ControllerKind::Keyboard)
+            .count();
+        bindings.check(n_keyboards)?;
+        Ok((config, level, bindings, replay))
+    }
+}
+
+fn main() -> ExitCode {
+    match Cli::parse().into_config() {
+        Ok((config, level, bindings, replay)) => {
+            run(config, level, bindings, replay);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Invalid configuration: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
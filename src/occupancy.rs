@@ -0,0 +1,200 @@
+use bevy::math::IVec2;
+use rand::Rng;
+
+use crate::{game::Board, level::Level};
+
+/// Free cells an apple can be placed on. The free cells are kept in a dense list
+/// and every cell knows its slot in that list, so occupying, releasing and
+/// sampling a uniformly random free cell are all O(1).
+#[derive(Clone, Debug)]
+pub struct Occupancy {
+    board: Board,
+    /// Cells of the level's apple zone, only these are ever free.
+    eligible: Vec<bool>,
+    free: Vec<IVec2>,
+    slots: Vec<Option<usize>>,
+}
+
+impl Occupancy {
+    pub fn new(level: &Level) -> Occupancy {
+        let board = level.board;
+        let mut occupancy = Occupancy {
+            board,
+            eligible: vec![false; board.n_cells()],
+            free: Vec::with_capacity(board.n_cells()),
+            slots: vec![None; board.n_cells()],
+        };
+
+        for cell in board.cells().filter(|cell| level.is_apple_cell(*cell)) {
+            let index = board.index(cell).unwrap();
+            occupancy.eligible[index] = true;
+            occupancy.release(cell);
+        }
+
+        occupancy
+    }
+
+    pub fn is_free(&self, cell: IVec2) -> bool {
+        self.board
+            .index(cell)
+            .is_some_and(|index| self.slots[index].is_some())
+    }
+
+    pub fn n_free(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Marks the cell as taken. Cells outside the apple zone are ignored.
+    pub fn occupy(&mut self, cell: IVec2) {
+        let Some(index) = self.board.index(cell) else {
+            return;
+        };
+        let Some(slot) = self.slots[index].take() else {
+            return;
+        };
+
+        self.free.swap_remove(slot);
+        if let Some(moved) = self.free.get(slot).copied() {
+            let moved_index = self.board.index(moved).unwrap();
+            self.slots[moved_index] = Some(slot);
+        }
+    }
+
+    /// Marks the cell as free again. Cells outside the apple zone are ignored.
+    pub fn release(&mut self, cell: IVec2) {
+        let Some(index) = self.board.index(cell) else {
+            return;
+        };
+        if !self.eligible[index] || self.slots[index].is_some() {
+            return;
+        }
+
+        self.slots[index] = Some(self.free.len());
+        self.free.push(cell);
+    }
+
+    /// A uniformly random free cell, `None` if the board is full.
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<IVec2> {
+        if self.free.is_empty() {
+            return None;
+        }
+
+        Some(self.free[rng.gen_range(0..self.free.len())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        controller,
+        game::{Direction, GameState},
+        level::LevelFile,
+        Config, ControllerKind,
+    };
+
+    /// Apple cells not covered by a snake, an apple or a crab.
+    fn n_uncovered(state: &GameState) -> usize {
+        let mut taken: HashSet<_> = state
+            .snakes
+            .iter()
+            .flat_map(|s| s.body.iter().copied())
+            .collect();
+        taken.extend(state.apples.iter().flatten().map(|a| a.cell));
+        taken.extend(state.crabs.iter().map(|c| c.cell));
+        state
+            .level
+            .board
+            .cells()
+            .filter(|cell| state.level.is_apple_cell(*cell) && !taken.contains(cell))
+            .count()
+    }
+
+    fn play(config: &Config, level: &Level) {
+        for seed in 0..10 {
+            let mut state = GameState::new(config, level, seed);
+            let mut controllers = controller::from_config(config, level);
+            assert_eq!(state.occupancy.n_free(), n_uncovered(&state));
+
+            controller::play(&mut state, &mut controllers, 2000, |state, _| {
+                assert_eq!(
+                    state.occupancy.n_free(),
+                    n_uncovered(state),
+                    "seed {seed}, tick {}",
+                    state.ticks()
+                );
+            });
+        }
+    }
+
+    fn config() -> Config {
+        let mut config = Config {
+            n_horizontal_cells: 21,
+            n_vertical_cells: 15,
+            initial_bodylength: 4,
+            n_players: 2,
+            controllers: vec![ControllerKind::ShortestPath, ControllerKind::Greedy],
+            n_apples: 3,
+            apple_lifetime: Some(30),
+            ..Default::default()
+        };
+        config.crabs.n_crabs = 3;
+        config.apple_kinds.poison_probability = 0.2;
+        config
+    }
+
+    #[test]
+    fn tracks_open_board() {
+        let config = config();
+        play(&config, &Level::open(&config));
+    }
+
+    #[test]
+    fn tracks_apple_zone() {
+        let config = config();
+        let file = LevelFile {
+            name: "Zone".to_string(),
+            direction: Direction::Right,
+            map: [
+                "###########",
+                "#....S....#",
+                "#..AAAAA..#",
+                "#.........#",
+                "#..AAAAA..#",
+                "#.........#",
+                "###########",
+            ]
+            .map(String::from)
+            .to_vec(),
+        };
+        let level = Level::parse(&file).unwrap();
+        level.check(&config).unwrap();
+        play(&config, &level);
+    }
+
+    #[test]
+    fn samples_only_free_cells() {
+        let config = config();
+        let level = Level::open(&config);
+        let mut occupancy = Occupancy::new(&level);
+        let n_free = occupancy.n_free();
+        occupancy.occupy(IVec2::ZERO);
+        occupancy.occupy(IVec2::ZERO);
+        occupancy.occupy(level.board.get_wall()[0]);
+        assert_eq!(occupancy.n_free(), n_free - 1);
+        assert!(!occupancy.is_free(IVec2::ZERO));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let cell = occupancy.sample(&mut rng).unwrap();
+            assert!(level.is_apple_cell(cell) && cell != IVec2::ZERO);
+        }
+
+        occupancy.release(IVec2::ZERO);
+        assert_eq!(occupancy.n_free(), n_free);
+    }
+}
@@ -1,57 +1,88 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, window::WindowResized};
 
 use crate::{
+    game::Board,
     geometry,
-    grid::Grid,
-    snake::{Position, Velocity},
-    Config, GameOverEvent,
+    grid::{Cell, Grid},
+    level::Level,
 };
 
+#[derive(Component)]
+pub struct Wall;
+
+fn grid_from_window(window: &Window, board: &Board) -> Grid {
+    let factor = 1.1;
+    let pixels_x = (window.resolution.width() / factor).floor();
+    let pixels_y = (window.resolution.height() / factor).floor();
+    Grid::new(pixels_x, pixels_y, board)
+}
+
 pub fn spawn_playing_ground(
     mut commands: Commands,
-    config: Res<Config>,
+    level: Res<Level>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     window: Query<&Window>,
 ) {
     // Define Grid
-    let factor = 1.1;
-    let resolution = &window.get_single().unwrap().resolution;
-    let pixels_x = (resolution.width() / factor).floor();
-    let pixels_y = (resolution.height() / factor).floor();
-    // let res = (res_x, res_y).min();
-    let grid = Grid::new(
-        pixels_x,
-        pixels_y,
-        config.n_horizontal_cells,
-        config.n_vertical_cells,
-    );
-
-    // Spawn Walls
-    for cell in grid.get_wall() {
-        let square = geometry::get_square(
-            grid.lambda,
-            cell.pos_x,
-            cell.pos_y,
-            &mut meshes,
-            &mut materials,
-        );
-        commands.spawn(square);
-    }
+    let grid = grid_from_window(window.get_single().unwrap(), &level.board);
+
+    spawn_walls(&mut commands, &grid, &level, &mut meshes, &mut materials);
 
     commands.insert_resource(grid);
 }
 
-pub fn snake_hits_wall(
+fn spawn_walls(
+    commands: &mut Commands,
+    grid: &Grid,
+    level: &Level,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+) {
+    for idx in &level.walls {
+        let cell = grid.get_cell(*idx);
+        let square = geometry::get_square(grid.lambda, cell.pos_x, cell.pos_y, meshes, materials);
+        commands.spawn((square, Wall, cell));
+    }
+}
+
+/// Replaces the wall entities after the walls of the level were edited.
+pub fn respawn_walls(
+    mut commands: Commands,
     grid: Res<Grid>,
-    snake: Query<(&Position, &Velocity)>,
-    mut ev_game_over: EventWriter<GameOverEvent>,
+    level: Res<Level>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    walls: Query<Entity, With<Wall>>,
 ) {
-    let snake = snake.get_single().unwrap();
+    for entity in &walls {
+        commands.entity(entity).despawn();
+    }
+
+    spawn_walls(&mut commands, &grid, &level, &mut meshes, &mut materials);
+}
+
+/// Recomputes the `Grid` for the new window size. The cell indices stay the same,
+/// only the positions and sizes on screen change.
+pub fn resize_grid(
+    mut grid: ResMut<Grid>,
+    level: Res<Level>,
+    window: Query<&Window>,
+    mut ev_resized: EventReader<WindowResized>,
+) {
+    if ev_resized.read().last().is_none() {
+        return;
+    }
+
+    *grid = grid_from_window(window.get_single().unwrap(), &level.board);
+}
 
-    let pos = snake.0;
-    let cell = grid.get_cell_from_position(pos.x, pos.y);
-    if !grid.is_inside_walls(cell.idx_x, cell.idx_y) {
-        ev_game_over.send(GameOverEvent());
+pub fn relayout_cells(grid: Res<Grid>, mut cells: Query<(&mut Transform, &mut Cell)>) {
+    for (mut t, mut c) in &mut cells {
+        let cell = grid.get_cell_from_index(c.idx_x, c.idx_y);
+        c.set(&cell);
+        t.translation.x = c.pos_x;
+        t.translation.y = c.pos_y;
+        t.scale = Vec3::new(grid.lambda, grid.lambda, 1.);
     }
 }
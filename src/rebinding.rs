@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+
+use crate::{
+    input::{Action, Binding, Bindings},
+    AppState, Config,
+};
+
+/// Walks through all actions and asks for a new key or button for each.
+#[derive(Resource)]
+pub struct Rebinding {
+    /// The bindings being edited, they only take effect once all are done.
+    pub bindings: Bindings,
+    /// The actions in the order they are asked for, with their set of steering bindings.
+    pub steps: Vec<(Action, usize)>,
+    pub current: usize,
+    /// Result of the last key press or of saving, shown below the list.
+    pub message: String,
+}
+
+impl Rebinding {
+    fn is_done(&self) -> bool {
+        self.current == self.steps.len()
+    }
+}
+
+#[derive(Component)]
+pub struct RebindingHud;
+
+fn step_name((action, slot): (Action, usize)) -> String {
+    match action.direction() {
+        Some(_) => format!("Player {} {action:?}", slot + 1),
+        None => format!("{action:?}"),
+    }
+}
+
+pub fn enter_rebinding(mut commands: Commands, bindings: Res<Bindings>) {
+    let steering =
+        (0..bindings.players.len()).flat_map(|slot| Action::STEERING.map(|action| (action, slot)));
+    let steps = steering
+        .chain([(Action::Pause, 0), (Action::Restart, 0)])
+        .collect();
+
+    commands.insert_resource(Rebinding {
+        bindings: bindings.clone(),
+        steps,
+        current: 0,
+        message: String::new(),
+    });
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.,
+                color: Color::rgb(1.00, 0.34, 0.20),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        RebindingHud,
+    ));
+}
+
+pub fn exit_rebinding(mut commands: Commands, hud: Query<Entity, With<RebindingHud>>) {
+    for entity in &hud {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<Rebinding>();
+}
+
+/// The next key or gamepad button replaces the binding of the same kind of
+/// the current action. Tab skips the action, Escape returns to the menu, which
+/// drops the changes unless all actions are done. Then the bindings are saved.
+pub fn rebind(
+    keycode: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    config: Res<Config>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keycode.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::Menu);
+        return;
+    }
+    if rebinding.is_done() {
+        return;
+    }
+    if keycode.just_pressed(KeyCode::Tab) {
+        rebinding.current += 1;
+    } else {
+        let pressed = keycode
+            .get_just_pressed()
+            .map(|key| Binding::Key(*key))
+            .chain(
+                buttons
+                    .get_just_pressed()
+                    .map(|button| Binding::Button(button.button_type)),
+            )
+            .next();
+        let Some(binding) = pressed else {
+            return;
+        };
+        if !binding.is_bindable() {
+            rebinding.message = format!("{binding:?} cannot be bound");
+            return;
+        }
+
+        let (action, slot) = rebinding.steps[rebinding.current];
+        rebinding.bindings.rebind(action, slot, binding);
+        rebinding.message = format!("{} is now {binding}", step_name((action, slot)));
+        rebinding.current += 1;
+    }
+
+    if rebinding.is_done() {
+        *bindings = rebinding.bindings.clone();
+        rebinding.message = match bindings.save(&config.bindings) {
+            Ok(()) => format!("Saved to {}", config.bindings.display()),
+            Err(err) => format!("Not saved, the bindings only last until the game ends: {err}"),
+        };
+    }
+}
+
+pub fn render_hud(rebinding: Res<Rebinding>, mut hud: Query<&mut Text, With<RebindingHud>>) {
+    let lines: Vec<_> = rebinding
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, (action, slot))| {
+            let marker = if i == rebinding.current { "> " } else { "  " };
+            format!(
+                "{marker}{}: {}",
+                step_name((*action, *slot)),
+                rebinding.bindings.describe(*action, *slot)
+            )
+        })
+        .collect();
+    let prompt = if rebinding.is_done() {
+        "Done | Escape: menu"
+    } else {
+        "Press a key or gamepad button for the marked action | Tab: skip | Escape: cancel"
+    };
+
+    let mut text = hud.single_mut();
+    text.sections[0].value = format!(
+        "Controls\n{prompt}\n\n{}\n\n{}",
+        lines.join("\n"),
+        rebinding.message
+    );
+}
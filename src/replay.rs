@@ -0,0 +1,194 @@
+use std::{collections::VecDeque, path::Path, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, ConfigError},
+    controller::{self, SnakeController},
+    game::{Direction, GameState, TickInput},
+    level::{Level, LevelFile},
+    Config,
+};
+
+/// Everything needed to play a game again exactly as it went. The level is
+/// kept as well, so the replay does not depend on the level file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: Config,
+    pub level: LevelFile,
+    /// Number of steps the game lasted.
+    pub n_ticks: u32,
+    pub inputs: Vec<TickInput>,
+}
+
+impl Replay {
+    /// The replay of the game so far.
+    pub fn of(state: &GameState) -> Replay {
+        Replay {
+            seed: state.seed,
+            config: state.config().clone(),
+            level: state.level.to_file(),
+            n_ticks: state.ticks(),
+            inputs: state.inputs.clone(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ConfigError> {
+        let content = config::read_file(path)?;
+        ron::from_str(&content).map_err(|err| ConfigError::InvalidReplay(err.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        config::write_ron(path, self, false)
+    }
+
+    /// The level of the replay, fails if it does not fit the config of the replay.
+    pub fn level(&self) -> Result<Level, ConfigError> {
+        let level = Level::parse(&self.level)?;
+        level.check(&self.config)?;
+        Ok(level)
+    }
+
+    /// One controller per snake that gives the recorded directions.
+    pub fn controllers(&self) -> Vec<Box<dyn SnakeController>> {
+        (0..self.config.n_players as usize)
+            .map(|snake| -> Box<dyn SnakeController> {
+                let inputs = self
+                    .inputs
+                    .iter()
+                    .filter(|input| input.1 == snake)
+                    .map(|input| (input.0, input.2))
+                    .collect();
+                Box::new(ReplayController { inputs })
+            })
+            .collect()
+    }
+
+    /// Plays the whole replay without a window and returns the final state.
+    pub fn play(&self) -> Result<GameState, ConfigError> {
+        let mut state = GameState::new(&self.config, &self.level()?, self.seed);
+        controller::play(&mut state, &mut self.controllers(), self.n_ticks, |_, _| {});
+        Ok(state)
+    }
+}
+
+/// Gives a snake the directions of a replay at the ticks they were given.
+pub struct ReplayController {
+    inputs: VecDeque<(u32, Direction)>,
+}
+
+impl SnakeController for ReplayController {
+    fn next_direction(&mut self, state: &GameState, _snake: usize) -> Option<Direction> {
+        match self.inputs.front() {
+            Some((tick, direction)) if *tick == state.ticks() => {
+                let direction = *direction;
+                self.inputs.pop_front();
+                Some(direction)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether games are recorded to a file or a recorded game is played.
+#[derive(Resource, Default)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    /// Every finished game is written to the file, replacing the one before.
+    Record(PathBuf),
+    Play(Box<Replay>),
+}
+
+impl ReplayMode {
+    pub fn is_playing(&self) -> bool {
+        matches!(self, ReplayMode::Play(_))
+    }
+}
+
+pub fn save_replay(state: Res<GameState>, mode: Res<ReplayMode>) {
+    if let ReplayMode::Record(path) = mode.as_ref() {
+        if let Err(err) = Replay::of(&state).save(path) {
+            error!("Cannot save the replay: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ControllerKind;
+
+    /// A game of two bots with crabs and expiring apples, stopped after
+    /// `max_ticks` if it is not over by then.
+    fn record(seed: u64, max_ticks: u32) -> GameState {
+        let mut config = Config {
+            n_horizontal_cells: 21,
+            n_vertical_cells: 15,
+            initial_bodylength: 4,
+            n_players: 2,
+            controllers: vec![ControllerKind::ShortestPath, ControllerKind::Greedy],
+            n_apples: 2,
+            apple_lifetime: Some(40),
+            ..Default::default()
+        };
+        config.crabs.n_crabs = 2;
+        let level = config.load_level().unwrap();
+        let mut state = GameState::new(&config, &level, seed);
+        let mut controllers = controller::from_config(&config, &level);
+        controller::play(&mut state, &mut controllers, max_ticks, |_, _| {});
+        state
+    }
+
+    fn assert_same(played: &GameState, recorded: &GameState) {
+        assert_eq!(played.ticks(), recorded.ticks());
+        assert_eq!(played.game_over, recorded.game_over);
+        assert_eq!(played.winner, recorded.winner);
+        assert_eq!(played.apples, recorded.apples);
+        assert_eq!(played.inputs, recorded.inputs);
+        for (a, b) in played.snakes.iter().zip(&recorded.snakes) {
+            assert_eq!(a.body, b.body);
+            assert_eq!(a.alive, b.alive);
+            assert_eq!(a.score.n_apples, b.score.n_apples);
+            assert_eq!(a.score.score, b.score.score);
+        }
+        let crabs = |state: &GameState| state.crabs.iter().map(|c| c.cell).collect::<Vec<_>>();
+        assert_eq!(crabs(played), crabs(recorded));
+    }
+
+    #[test]
+    fn plays_the_recorded_game() {
+        for seed in 0..10 {
+            let recorded = record(seed, 2000);
+            let played = Replay::of(&recorded).play().unwrap();
+            assert_same(&played, &recorded);
+        }
+    }
+
+    #[test]
+    fn plays_an_unfinished_game_up_to_its_last_tick() {
+        let recorded = record(0, 50);
+        let played = Replay::of(&recorded).play().unwrap();
+        assert_same(&played, &recorded);
+    }
+
+    #[test]
+    fn survives_the_file_format() {
+        let recorded = record(3, 2000);
+        let content = ron::to_string(&Replay::of(&recorded)).unwrap();
+        let replay: Replay = ron::from_str(&content).unwrap();
+        assert_same(&replay.play().unwrap(), &recorded);
+    }
+
+    #[test]
+    fn rejects_a_level_that_does_not_fit() {
+        let mut replay = Replay::of(&record(0, 10));
+        replay.config.n_apples = 10_000;
+        assert!(matches!(
+            replay.play(),
+            Err(ConfigError::InvalidAppleCount { .. })
+        ));
+    }
+}
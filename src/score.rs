@@ -1,10 +1,12 @@
 use bevy::prelude::*;
 
-use crate::{apples::AppleEatenEvent, grid::Grid, snake::Speed, Config};
+use crate::{game::GameState, grid::Grid};
 
-#[derive(Resource)]
+#[derive(Clone, Debug)]
 pub struct Score {
     pub n_apples: u32,
+    /// Crabs eaten from behind.
+    pub n_crabs: u32,
     pub score: f32,
 }
 
@@ -12,6 +14,7 @@ impl Default for Score {
     fn default() -> Self {
         Score {
             n_apples: 0,
+            n_crabs: 0,
             score: 0.,
         }
     }
@@ -20,15 +23,15 @@ impl Default for Score {
 #[derive(Event)]
 pub struct ScoreIncreasedEvent;
 
-pub fn spawn_score(mut commands: Commands, grid: Res<Grid>) {
-    // spawn resource
-    commands.insert_resource(Score { ..default() });
+#[derive(Component)]
+pub struct ScoreText;
 
+pub fn spawn_score(mut commands: Commands, grid: Res<Grid>) {
     // spawn text
     let cell_top_left = grid.get_cell_from_index(-grid.max_idx_x + 3, -grid.max_idx_y + 1);
     let pos_x = grid.screen_width / 2. + cell_top_left.pos_x;
     let pos_y = grid.screen_height / 2. + cell_top_left.pos_y;
-    commands.spawn(
+    commands.spawn((
         TextBundle::from_section(
             format!("Score: {}", 0),
             TextStyle {
@@ -44,29 +47,40 @@ pub fn spawn_score(mut commands: Commands, grid: Res<Grid>) {
             height: Val::Px(grid.lambda),
             ..default()
         }),
-    );
+        ScoreText,
+    ));
 }
 
-pub fn increment_score(
-    config: Res<Config>,
-    mut score: ResMut<Score>,
-    speed: Res<Speed>,
-    mut ev_apple_eaten: EventReader<AppleEatenEvent>,
-    mut ev_score_increased: EventWriter<ScoreIncreasedEvent>,
-) {
-    for _ in ev_apple_eaten.read() {
-        score.n_apples += config.score_increment;
-        score.score += config.score_increment as f32 * speed.in_blocks() * speed.in_blocks();
-        ev_score_increased.send(ScoreIncreasedEvent);
-    }
+pub fn relayout_score(grid: Res<Grid>, mut text: Query<(&mut Text, &mut Style), With<ScoreText>>) {
+    let (mut text, mut style) = text.single_mut();
+    let cell_top_left = grid.get_cell_from_index(-grid.max_idx_x + 3, -grid.max_idx_y + 1);
+    style.top = Val::Px(grid.screen_height / 2. + cell_top_left.pos_y);
+    style.left = Val::Px(grid.screen_width / 2. + cell_top_left.pos_x);
+    style.height = Val::Px(grid.lambda);
+    text.sections[0].style.font_size = grid.lambda * 1.30;
 }
 
-pub fn render_score(score: Res<Score>, mut text: Query<&mut Text>) {
+pub fn render_score(state: Res<GameState>, mut text: Query<&mut Text, With<ScoreText>>) {
     let mut text = text.single_mut();
     let text = &mut text.sections[0].value;
 
-    *text = format!(
-        "Apples: {:0>7} | Score: {:0>10}",
-        score.n_apples, score.score as u32
-    );
+    *text = match state.snakes.as_slice() {
+        [snake] => format!(
+            "Apples: {:0>7} | Score: {:0>10}",
+            snake.score.n_apples, snake.score.score as u32
+        ),
+        snakes => snakes
+            .iter()
+            .enumerate()
+            .map(|(i, snake)| {
+                format!(
+                    "P{}: {:0>4} | {:0>8}",
+                    i + 1,
+                    snake.score.n_apples,
+                    snake.score.score as u32
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("     "),
+    };
 }
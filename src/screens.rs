@@ -0,0 +1,332 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::GameState,
+    grid::Grid,
+    highscores::{self, HighScores, NameEntry},
+    input::{Action, ActionEvent, Bindings},
+    replay::ReplayMode,
+    AppState, Config, Difficulty, GameOverEvent, LevelCompleteEvent,
+};
+
+#[derive(Component)]
+pub struct MenuScreen;
+
+#[derive(Component)]
+pub struct PauseScreen;
+
+#[derive(Component)]
+pub struct GameOverScreen;
+
+#[derive(Component)]
+pub struct ResultsScreen;
+
+#[derive(Component)]
+pub struct HighScoreScreen;
+
+fn spawn_screen(commands: &mut Commands, grid: &Grid, marker: impl Component, text: String) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size: grid.lambda * 1.30,
+                        color: Color::rgb(1.00, 0.34, 0.20),
+                        ..default()
+                    },
+                )
+                .with_text_justify(JustifyText::Center),
+            );
+        });
+}
+
+pub fn despawn_screen<T: Component>(mut commands: Commands, screen: Query<Entity, With<T>>) {
+    for entity in &screen {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Name of the preset the config matches.
+pub fn difficulty_name(config: &Config) -> String {
+    match Difficulty::of(config) {
+        Some(difficulty) => format!("{difficulty:?}"),
+        None => "Custom".to_string(),
+    }
+}
+
+pub fn spawn_menu(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    config: Res<Config>,
+    replay: Res<ReplayMode>,
+) {
+    let text = if replay.is_playing() {
+        format!(
+            "Snakes and Crabs\n\n\
+             Press Space or tap to play the replay\n\
+             Press B to change the controls\n\
+             Press H for the high scores\n\n\
+             Difficulty: {}",
+            difficulty_name(&config)
+        )
+    } else {
+        format!(
+            "Snakes and Crabs\n\n\
+             Press Space or tap to start\n\
+             Press E to edit the level\n\
+             Press B to change the controls\n\
+             Press H for the high scores\n\n\
+             Difficulty: {}\n\
+             Press 1 to 4 for Easy, Normal, Hard or Insane",
+            difficulty_name(&config)
+        )
+    };
+    spawn_screen(&mut commands, &grid, MenuScreen, text);
+}
+
+pub fn spawn_pause(mut commands: Commands, grid: Res<Grid>, bindings: Res<Bindings>) {
+    spawn_screen(
+        &mut commands,
+        &grid,
+        PauseScreen,
+        format!(
+            "Paused\n\nPress {} to resume",
+            bindings.describe(Action::Pause, 0)
+        ),
+    );
+}
+
+/// The headline of a finished game, with several players it names the winner.
+fn headline(state: &GameState, single_player: &str) -> String {
+    if state.snakes.len() == 1 {
+        return single_player.to_string();
+    }
+
+    match state.winner {
+        Some(winner) => format!("Player {} wins!", winner + 1),
+        None => "Draw!".to_string(),
+    }
+}
+
+/// One line of apples and score per player.
+fn scores(state: &GameState) -> String {
+    match state.snakes.as_slice() {
+        [snake] => format!(
+            "Apples: {} | Crabs: {} | Missed: {} | Score: {}",
+            snake.score.n_apples, snake.score.n_crabs, state.n_missed, snake.score.score as u32
+        ),
+        snakes => snakes
+            .iter()
+            .enumerate()
+            .map(|(i, snake)| {
+                format!(
+                    "Player {}: Apples: {} | Crabs: {} | Score: {}",
+                    i + 1,
+                    snake.score.n_apples,
+                    snake.score.n_crabs,
+                    snake.score.score as u32
+                )
+            })
+            .chain(std::iter::once(format!("Missed: {}", state.n_missed)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// How to go on from a finished game, nothing while names are entered.
+fn restart_hint(bindings: &Bindings, name_entry: Option<Res<NameEntry>>, verb: &str) -> String {
+    match name_entry {
+        Some(_) => String::new(),
+        None => format!(
+            "\n\nPress {} to {verb} or Escape for the menu",
+            bindings.describe(Action::Restart, 0)
+        ),
+    }
+}
+
+pub fn spawn_game_over(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    state: Res<GameState>,
+    bindings: Res<Bindings>,
+    name_entry: Option<Res<NameEntry>>,
+) {
+    spawn_screen(
+        &mut commands,
+        &grid,
+        GameOverScreen,
+        format!(
+            "{}\n\n{}\nSeed: {}{}",
+            headline(&state, "Game Over"),
+            scores(&state),
+            state.seed,
+            restart_hint(&bindings, name_entry, "restart")
+        ),
+    );
+}
+
+pub fn spawn_results(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    state: Res<GameState>,
+    bindings: Res<Bindings>,
+    name_entry: Option<Res<NameEntry>>,
+) {
+    spawn_screen(
+        &mut commands,
+        &grid,
+        ResultsScreen,
+        format!(
+            "{}\n\n{}\nTime: {:.1}s | Seed: {}{}",
+            headline(&state, "Level complete!"),
+            scores(&state),
+            state.elapsed,
+            state.seed,
+            restart_hint(&bindings, name_entry, "play again")
+        ),
+    );
+}
+
+/// The table of the mode and settings of the game just played.
+pub fn spawn_high_scores(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    state: Res<GameState>,
+    high_scores: Res<HighScores>,
+    bindings: Res<Bindings>,
+) {
+    let config = state.config();
+    let entries = high_scores.entries(config);
+    let lines = match entries {
+        [] => "No high scores yet".to_string(),
+        entries => entries
+            .iter()
+            .enumerate()
+            .map(|(rank, entry)| {
+                format!(
+                    "{}. {} | Apples: {} | Score: {} | {}",
+                    rank + 1,
+                    entry.name,
+                    entry.n_apples,
+                    entry.score as u32,
+                    entry.date
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+
+    spawn_screen(
+        &mut commands,
+        &grid,
+        HighScoreScreen,
+        format!(
+            "High Scores - {}\n\n{lines}{}",
+            highscores::mode(config),
+            restart_hint(&bindings, None, "play")
+        ),
+    );
+}
+
+/// Whether the action was requested, all pending actions are consumed.
+fn pressed(ev_action: &mut EventReader<ActionEvent>, action: Action) -> bool {
+    ev_action.read().filter(|ev| ev.action == action).count() > 0
+}
+
+pub fn start_game(
+    keycode: Res<ButtonInput<KeyCode>>,
+    mut ev_action: EventReader<ActionEvent>,
+    mut config: ResMut<Config>,
+    replay: Res<ReplayMode>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // A replay is played with the settings and the level it was recorded with
+    let difficulty_keys = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+    ];
+    for (key, difficulty) in difficulty_keys.into_iter().zip(Difficulty::ALL) {
+        if keycode.just_pressed(key) && !replay.is_playing() {
+            difficulty.apply(&mut config);
+        }
+    }
+
+    if keycode.any_just_pressed([KeyCode::Space, KeyCode::Enter])
+        || pressed(&mut ev_action, Action::Restart)
+    {
+        next_state.set(AppState::Playing);
+    } else if keycode.just_pressed(KeyCode::KeyE) && !replay.is_playing() {
+        next_state.set(AppState::Editor);
+    } else if keycode.just_pressed(KeyCode::KeyB) {
+        next_state.set(AppState::Rebinding);
+    } else if keycode.just_pressed(KeyCode::KeyH) {
+        next_state.set(AppState::HighScores);
+    }
+}
+
+pub fn toggle_pause(
+    mut ev_action: EventReader<ActionEvent>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if pressed(&mut ev_action, Action::Pause) {
+        match state.get() {
+            AppState::Playing => next_state.set(AppState::Paused),
+            AppState::Paused => next_state.set(AppState::Playing),
+            _ => {}
+        }
+    }
+}
+
+/// Stops the virtual clock, so the fixed timestep does not pile up ticks
+/// which would make the snake jump on resume.
+pub fn freeze_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+pub fn unfreeze_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+pub fn game_over(
+    mut ev_game_over: EventReader<GameOverEvent>,
+    mut ev_level_complete: EventReader<LevelCompleteEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let won = ev_level_complete.read().last().is_some();
+    let lost = ev_game_over.read().last().is_some();
+    if won {
+        next_state.set(AppState::LevelComplete);
+    } else if lost {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+pub fn restart_or_leave(
+    keycode: Res<ButtonInput<KeyCode>>,
+    mut ev_action: EventReader<ActionEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if pressed(&mut ev_action, Action::Restart) {
+        next_state.set(AppState::Playing);
+    } else if keycode.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::Menu);
+    }
+}
@@ -1,289 +1,267 @@
 use crate::{
-    apples::AppleEatenEvent,
-    geometry::{self, get_square},
+    apples::{self, Apple, AppleEatenEvent, AppleExpiredEvent, RelocateAppleEvent},
+    controller::{self, SnakeController},
+    game::{GameEvent, GameState},
+    geometry,
     grid::{Cell, Grid},
-    score::{Score, ScoreIncreasedEvent},
-    Config, GameOverEvent,
+    input::ActionEvent,
+    replay::ReplayMode,
+    score::ScoreIncreasedEvent,
+    Config, GameOverEvent, LevelCompleteEvent,
 };
 
+use std::time::Duration;
+
 use bevy::prelude::*;
 
+/// Marks the segment that leads the snake. It also carries `Body(0)`.
 #[derive(Component)]
-pub struct Velocity {
-    pub x: f32,
-    pub y: f32,
-    direction: Direction,
-}
+pub struct Head;
 
 #[derive(Component)]
-pub struct Position {
-    pub x: f32,
-    pub y: f32,
-}
+pub struct Body(pub u32);
 
-#[derive(Resource)]
-pub struct Speed {
-    in_blocks: f32,
-    res: f32,
-}
+/// The index of the snake a segment belongs to in `GameState::snakes`.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct Player(pub usize);
 
-impl Speed {
-    pub fn in_blocks(&self) -> f32 {
-        self.in_blocks
-    }
+/// The controller of each player, asked for a direction before every step of the simulation.
+#[derive(Resource, Default)]
+pub struct Controllers(pub Vec<Box<dyn SnakeController>>);
 
-    pub fn in_pixels(&self) -> f32 {
-        self.in_blocks * self.res
-    }
+/// Sent once per tick and snake with the cell its head moved to.
+#[derive(Event)]
+pub struct MoveEvent {
+    pub player: usize,
+    pub cell: Cell,
+}
 
-    fn set_speed_in_blocks(&mut self, speed: f32) {
-        self.in_blocks = speed;
+fn snake_color(player: usize) -> Color {
+    match player {
+        0 => Color::rgb(6.25, 9.4, 9.1),
+        _ => Color::rgb(9.4, 5.0, 1.0),
     }
 }
 
-#[derive(Component)]
-pub struct Body(u32);
-
-enum Direction {
-    Vertical,
-    Horizontal,
+fn spawn_segment(
+    commands: &mut Commands,
+    grid: &Grid,
+    player: usize,
+    i: usize,
+    idx: IVec2,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+) {
+    let cell = grid.get_cell(idx);
+    let square = geometry::get_colored_square(
+        grid.lambda,
+        cell.pos_x,
+        cell.pos_y,
+        snake_color(player),
+        meshes,
+        materials,
+    );
+    let mut segment = commands.spawn((square, Body(i as u32), Player(player), cell));
+    if i == 0 {
+        segment.insert(Head);
+    }
 }
 
-#[derive(Event)]
-pub struct MoveEvent(pub Vec<Cell>);
-
 pub fn spawn_snake(
     grid: Res<Grid>,
     config: Res<Config>,
+    state: Res<GameState>,
+    replay: Res<ReplayMode>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let res = grid.lambda;
-    let speed = Speed {
-        res,
-        in_blocks: config.initial_speed,
-    };
+    for (player, snake) in state.snakes.iter().enumerate() {
+        for (i, idx) in snake.body.iter().enumerate() {
+            spawn_segment(
+                &mut commands,
+                &grid,
+                player,
+                i,
+                *idx,
+                &mut meshes,
+                &mut materials,
+            );
+        }
+    }
 
-    // Spawn Snake
-    let mut cell = grid.get_cell_from_position(0., 0.);
-    let square = geometry::get_square(res, cell.pos_x, cell.pos_y, &mut meshes, &mut materials);
-    commands.spawn((
-        square,
-        Position {
-            x: cell.pos_x,
-            y: cell.pos_y,
-        },
-        Velocity {
-            x: speed.in_pixels(),
-            y: 0.,
-            direction: Direction::Horizontal,
-        },
-        cell.clone(),
-    ));
+    let controllers = match replay.as_ref() {
+        ReplayMode::Play(replay) => replay.controllers(),
+        _ => controller::from_config(&config, &state.level),
+    };
+    commands.insert_resource(Controllers(controllers));
+}
 
-    for i in 1..config.initial_bodylength {
-        // Spawn body
-        // ... First Fragment
-        cell = grid.get_cell_from_index(cell.idx_x - 1, cell.idx_y);
-        let square = geometry::get_square(res, cell.pos_x, cell.pos_y, &mut meshes, &mut materials);
-        commands.spawn((square, Body(i), cell.clone()));
+pub fn despawn_snake(mut commands: Commands, body: Query<Entity, With<Body>>) {
+    for entity in &body {
+        commands.entity(entity).despawn();
     }
-
-    commands.insert_resource(speed);
 }
 
+/// Advances the simulation by exactly one cell. Runs in `FixedUpdate`, so
+/// the number of cells travelled never depends on the frame rate.
+#[allow(clippy::too_many_arguments)]
 pub fn move_snake(
-    time: Res<Time>,
     grid: Res<Grid>,
+    mut state: ResMut<GameState>,
+    mut controllers: ResMut<Controllers>,
+    apples: Query<(Entity, &Apple)>,
     mut ev_move: EventWriter<MoveEvent>,
-    mut head: Query<(&mut Transform, &mut Position, &Velocity, &mut Cell)>,
+    mut ev_apple_eaten: EventWriter<AppleEatenEvent>,
+    mut ev_apple_expired: EventWriter<AppleExpiredEvent>,
+    mut ev_relocate_apple: EventWriter<RelocateAppleEvent>,
+    mut ev_score_increased: EventWriter<ScoreIncreasedEvent>,
+    mut ev_level_complete: EventWriter<LevelCompleteEvent>,
+    mut ev_game_over: EventWriter<GameOverEvent>,
 ) {
-    // Move Head
-    let delta_t = time.delta_seconds();
-    let head = head.get_single_mut().unwrap();
-
-    let mut transform = head.0;
-    let mut pos = head.1;
-    let vel = head.2;
-    pos.x += vel.x * delta_t;
-    pos.y += vel.y * delta_t;
-
-    let new_cell = grid.get_cell_from_position(pos.x, pos.y);
-
-    let mut head_cell = head.3;
-    let old_cell = head_cell.clone();
-    head_cell.set(&new_cell);
-
-    transform.translation.x = new_cell.pos_x;
-    transform.translation.y = new_cell.pos_y;
-
-    if !head_cell.eq(&old_cell) {
-        let mut trace = vec![old_cell.clone()];
-
-        if old_cell.idx_x != head_cell.idx_x {
-            for idx_x in get_range(old_cell.idx_x, new_cell.idx_x) {
-                trace.push(grid.get_cell_from_index(idx_x, old_cell.idx_y))
+    let inputs = controller::next_directions(&mut controllers.0, &state);
+    for ev in state.step(&inputs) {
+        match ev {
+            GameEvent::Moved { snake, to, .. } => {
+                ev_move.send(MoveEvent {
+                    player: snake,
+                    cell: grid.get_cell(to),
+                });
             }
-        }
-        if old_cell.idx_y != head_cell.idx_y {
-            for idx_y in get_range(old_cell.idx_y, new_cell.idx_y) {
-                trace.push(grid.get_cell_from_index(old_cell.idx_x, idx_y))
+            GameEvent::AppleEaten {
+                apple, cell, kind, ..
+            } => {
+                if let Some(entity) = apples::find_apple(&apples, apple) {
+                    ev_apple_eaten.send(AppleEatenEvent {
+                        entity,
+                        cell: grid.get_cell(cell),
+                        kind,
+                    });
+                }
+            }
+            GameEvent::AppleRelocated { apple, cell, kind } => {
+                if let Some(entity) = apples::find_apple(&apples, apple) {
+                    ev_relocate_apple.send(RelocateAppleEvent {
+                        entity,
+                        cell: grid.get_cell(cell),
+                        kind,
+                    });
+                }
+            }
+            GameEvent::AppleExpired { apple, cell } => {
+                if let Some(entity) = apples::find_apple(&apples, apple) {
+                    ev_apple_expired.send(AppleExpiredEvent {
+                        entity,
+                        cell: grid.get_cell(cell),
+                    });
+                }
+            }
+            GameEvent::Shrunk { .. }
+            | GameEvent::Crashed { .. }
+            | GameEvent::CrabMoved { .. }
+            | GameEvent::CrabEaten { .. }
+            | GameEvent::AppleStolen { .. } => {}
+            GameEvent::ScoreIncreased { .. } => {
+                ev_score_increased.send(ScoreIncreasedEvent);
+            }
+            GameEvent::SpeedChanged { .. } => {}
+            GameEvent::LevelComplete => {
+                ev_level_complete.send(LevelCompleteEvent);
+            }
+            GameEvent::GameOver => {
+                ev_game_over.send(GameOverEvent());
             }
         }
-
-        ev_move.send(MoveEvent(trace));
     }
 }
 
-fn get_range(old: i32, new: i32) -> Box<dyn Iterator<Item = i32>> {
-    let direction = if new - old > 0 { 1 } else { -1 };
-
-    if direction > 0 {
-        Box::new((old + direction)..new)
-    } else {
-        Box::new(((new - direction)..old).rev())
+/// `GameState::speed` is the number of ticks per second of the fixed schedule.
+pub fn set_tick_rate(state: Res<GameState>, mut time: ResMut<Time<Fixed>>) {
+    let timestep = Duration::from_secs_f64(1. / state.speed as f64);
+    if time.timestep() != timestep {
+        time.set_timestep(timestep);
     }
 }
 
+/// Mirrors the cells of the simulated snakes onto the segment entities.
 pub fn move_body(
+    grid: Res<Grid>,
+    state: Res<GameState>,
     mut ev_move: EventReader<MoveEvent>,
-    mut body: Query<(&mut Transform, &mut Cell, &Body)>,
+    mut body: Query<(&mut Transform, &mut Cell, &Body, &Player)>,
 ) {
-    for ev in ev_move.read() {
-        let mut trace = ev.0.clone();
-
-        let mut sorted: Vec<_> = body.iter_mut().collect();
-        sorted.sort_by(|(_, _, Body(b1)), (_, _, Body(b2))| b1.cmp(b2));
-
-        let mut target_index = trace.len() - 1;
-
-        for (t, c, _) in &mut sorted {
-            let new_cell = trace[target_index].clone();
-            trace[target_index].set(c);
-
-            if target_index != 0 {
-                target_index -= 1;
-            } else {
-                target_index = trace.len() - 1;
-            }
-
-            c.set(&new_cell);
+    if ev_move.read().last().is_none() {
+        return;
+    }
 
+    for (mut t, mut c, Body(i), Player(player)) in &mut body {
+        if let Some(idx) = state.snakes[*player].body.get(*i as usize) {
+            c.set(&grid.get_cell(*idx));
             t.translation.x = c.pos_x;
             t.translation.y = c.pos_y;
         }
     }
 }
 
-pub fn steer_snake(
-    speed: Res<Speed>,
-    mut snake: Query<&mut Velocity, With<Position>>,
-    keycode: Res<ButtonInput<KeyCode>>,
-) {
-    keycode
-        .get_just_pressed()
-        .for_each(|k| handle_steering(&speed, &mut snake, k));
-}
-
-fn handle_steering(
-    speed: &Res<Speed>,
-    snake: &mut Query<&mut Velocity, With<Position>>,
-    keycode: &KeyCode,
-) {
-    let mut vel = snake.get_single_mut().unwrap();
-
-    match keycode {
-        KeyCode::KeyW | KeyCode::ArrowUp => {
-            if let Direction::Horizontal = vel.direction {
-                vel.x = 0.;
-                vel.y = speed.in_pixels();
-                vel.direction = Direction::Vertical
-            }
-        }
-        KeyCode::KeyA | KeyCode::ArrowLeft => {
-            if let Direction::Vertical = vel.direction {
-                vel.x = -speed.in_pixels();
-                vel.y = 0.;
-                vel.direction = Direction::Horizontal
-            }
-        }
-        KeyCode::KeyS | KeyCode::ArrowDown => {
-            if let Direction::Horizontal = vel.direction {
-                vel.x = 0.;
-                vel.y = -speed.in_pixels();
-                vel.direction = Direction::Vertical
-            }
-        }
-        KeyCode::KeyD | KeyCode::ArrowRight => {
-            if let Direction::Vertical = vel.direction {
-                vel.x = speed.in_pixels();
-                vel.y = 0.;
-                vel.direction = Direction::Horizontal
-            }
-        }
-        _ => {}
-    }
-}
-
-pub fn snake_hits_itself(
-    mut ev_move: EventReader<MoveEvent>,
-    head: Query<(&Cell, &Position)>,
-    body: Query<(&Cell, &Body)>,
-    mut ev_game_over: EventWriter<GameOverEvent>,
-) {
-    for ev in ev_move.read() {
-        for (body_cell, _) in &body {
-            for trace_cell in &ev.0 {
-                if body_cell == trace_cell {
-                    ev_game_over.send(GameOverEvent());
-                }
-            }
-        }
-    }
-
-    let (head_cell, _) = head.get_single().unwrap();
-    for (body_cell, _) in &body {
-        if body_cell == head_cell {
-            ev_game_over.send(GameOverEvent());
+/// Hands the pressed keys to the controllers, only keyboard controllers react.
+pub fn steer_snake(mut controllers: ResMut<Controllers>, mut ev_action: EventReader<ActionEvent>) {
+    for ev in ev_action.read() {
+        let (Some(slot), Some(direction)) = (ev.slot, ev.action.direction()) else {
+            continue;
+        };
+        for controller in &mut controllers.0 {
+            controller.steer(slot, direction);
         }
     }
 }
 
+/// Spawns the segments the simulation added since the last frame.
 pub fn snake_grows(
     mut commands: Commands,
-    config: Res<Config>,
     grid: Res<Grid>,
+    state: Res<GameState>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    body: Query<(&Cell, &Body)>,
-    mut ev_eaten: EventReader<AppleEatenEvent>,
+    body: Query<&Player, With<Body>>,
+    mut ev_move: EventReader<MoveEvent>,
 ) {
-    for _ in ev_eaten.read() {
-        let (last, Body(n)) = body.iter().last().unwrap();
-        for i in (n + 1)..(n + 1 + config.n_elements_per_apple) {
-            let square = get_square(
-                grid.lambda,
-                last.pos_x,
-                last.pos_y,
+    if ev_move.read().last().is_none() {
+        return;
+    }
+
+    let mut n_segments = vec![0; state.snakes.len()];
+    for Player(player) in &body {
+        n_segments[*player] += 1;
+    }
+    for (player, snake) in state.snakes.iter().enumerate() {
+        for (i, idx) in snake.body.iter().enumerate().skip(n_segments[player]) {
+            spawn_segment(
+                &mut commands,
+                &grid,
+                player,
+                i,
+                *idx,
                 &mut meshes,
                 &mut materials,
             );
-            commands.spawn((square, Body(i), last.clone()));
         }
     }
 }
 
-pub fn speed_up(
-    config: Res<Config>,
-    score: Res<Score>,
-    mut speed: ResMut<Speed>,
-    mut ev_score_increased: EventReader<ScoreIncreasedEvent>,
+/// Despawns the segments a poison apple removed from the simulated snakes.
+pub fn snake_shrinks(
+    mut commands: Commands,
+    state: Res<GameState>,
+    body: Query<(Entity, &Body, &Player)>,
+    mut ev_move: EventReader<MoveEvent>,
 ) {
-    for _ in ev_score_increased.read() {
-        if score.n_apples % (3 * config.score_increment) == 0 {
-            let old_speed = speed.in_blocks();
-            speed.set_speed_in_blocks(old_speed * 1.1);
+    if ev_move.read().last().is_none() {
+        return;
+    }
+
+    for (entity, Body(i), Player(player)) in &body {
+        if *i as usize >= state.snakes[*player].body.len() {
+            commands.entity(entity).despawn();
         }
     }
 }